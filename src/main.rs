@@ -1,23 +1,41 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use std::vec;
 
-use crate::monitor::{Monitor, MonitorKind};
+use crate::monitor::{Monitor, MonitorKind, NetDevBackend, ProcStatKind, SnmpProto, TcpDiagBy, TopBy};
 use crate::monitors::diskstat::DiskStatsMonitor;
+use crate::monitors::ethtool::EthtoolMonitor;
 use crate::monitors::interrupts::InterruptsMonitor;
+use crate::monitors::kthreads::KernelThreadsMonitor;
 use crate::monitors::memstat::MeminfoMonitor;
+use crate::monitors::netdev_info::NetDevInfoMonitor;
+use crate::monitors::netdev_link::NetlinkStatsMonitor;
 use crate::monitors::netdev_stat::NetSysfsStatsMonitor;
-use crate::monitors::proc::ProcessSchedMonitor;
+use crate::monitors::netstat::NetstatMonitor;
+use crate::monitors::proc::{ProcLabels, ProcessSchedMonitor};
+use crate::monitors::proc_fd::{ProcFdCollector, ProcLimitsCollector};
+use crate::monitors::proc_io::ProcIoCollector;
+use crate::monitors::proc_mem::{ProcNumaCollector, ProcSmapsCollector};
+use crate::monitors::proc_perf::ProcPerfCollector;
+use crate::monitors::proc_policy::ProcPolicyCollector;
+use crate::monitors::proc_sampler::ThreadSampler;
+use crate::monitors::proc_sock::ProcSocketsCollector;
+use crate::monitors::proc_top::TopProcessesMonitor;
 use crate::monitors::queues::NetSysfsQueuesMonitor;
 use crate::monitors::snmp::SNMPMonitor;
+use crate::monitors::snmp6::Snmp6Monitor;
+use crate::monitors::sockstat::SockstatMonitor;
+use crate::monitors::socktable::SocketTableMonitor;
 use crate::monitors::softirqs::SoftirqsMonitor;
 use crate::monitors::softnet_stat::SoftnetStatMonitor;
+use crate::monitors::tcp_diag::TcpDiagMonitor;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::routing::get;
 use axum::Router;
 use clap::Parser;
-use log::{error, info};
+use log::{error, info, warn};
 use prometheus::Registry;
 use prometheus::TextEncoder;
 use tokio::time::interval;
@@ -31,8 +49,65 @@ struct Cli {
     monitors: Vec<MonitorKind>,
     #[arg(long, default_value_t = 5)]
     interval: u64,
-    #[arg(long, default_value = "ping")]
-    proc_name: String,
+    #[arg(long, default_value = "ping", value_delimiter = ',')]
+    proc_name: Vec<String>,
+    /// Track matched PIDs via the netlink proc connector instead of scanning /proc every tick
+    #[arg(long)]
+    proc_events: bool,
+    /// With --proc-events, reconcile with a full /proc scan every N ticks
+    #[arg(long, default_value_t = 12)]
+    proc_rescan_ticks: u64,
+    /// Re-read comm of every PID instead of using the /proc scan cache every N scans, and the
+    /// cgroup of matched PIDs every N ticks
+    #[arg(long, default_value_t = 12)]
+    proc_revalidate_ticks: u64,
+    /// Attach cgroup, container_id and unit labels to per-process metrics
+    #[arg(long)]
+    proc_cgroup_labels: bool,
+    /// Also track all descendants of matched processes and export per-root sums
+    #[arg(long)]
+    proc_descendants: bool,
+    /// Sample thread states and wait channels of matched processes at this rate (0 = off)
+    #[arg(long, default_value_t = 0)]
+    sample_hz: u32,
+    /// Number of processes exported by the host-wide proc-top monitor
+    #[arg(long, default_value_t = 10)]
+    top_n: usize,
+    /// Counter proc-top ranks processes by, over the last interval
+    #[arg(long, value_enum, default_value = "cpu")]
+    top_by: TopBy,
+    #[arg(long = "proc-stat", value_delimiter = ',', value_enum)]
+    proc_stats: Vec<ProcStatKind>,
+    /// JSON file with the expected scheduling policy, priority, CPUs and memory nodes per --proc-name selector
+    #[arg(long)]
+    sched_policy: Option<PathBuf>,
+    /// Sections of /proc/net/snmp exported as snmp{proto,key} (default: all)
+    #[arg(long = "snmp-proto", value_delimiter = ',', value_enum)]
+    snmp_protos: Vec<SnmpProto>,
+    /// Keys of /proc/net/netstat to export, e.g. ListenOverflows,TCPTimeouts (default: all)
+    #[arg(long = "netstat-key", value_delimiter = ',')]
+    netstat_keys: Vec<String>,
+    /// Local ports with per-port accept queue and UDP drop metrics in the socket-table monitor
+    #[arg(long = "socket-port", value_delimiter = ',')]
+    socket_ports: Vec<u16>,
+    /// Read interface counters from sysfs (devices only) or rtnetlink (every link type)
+    #[arg(long, value_enum, default_value = "sysfs")]
+    netdev_backend: NetDevBackend,
+    /// With --netdev-backend netlink, link kinds to include, e.g. device,veth,bridge,bond (default: all)
+    #[arg(long = "netdev-kind", value_delimiter = ',')]
+    netdev_kinds: Vec<String>,
+    /// Interfaces queried by the ethtool monitor (default: all but lo)
+    #[arg(long = "ethtool-iface", value_delimiter = ',')]
+    ethtool_ifaces: Vec<String>,
+    /// Local ports whose TCP connections the tcp-diag monitor reports
+    #[arg(long = "tcp-diag-sport", value_delimiter = ',')]
+    tcp_diag_sports: Vec<u16>,
+    /// Remote ports whose TCP connections the tcp-diag monitor reports
+    #[arg(long = "tcp-diag-dport", value_delimiter = ',')]
+    tcp_diag_dports: Vec<u16>,
+    /// Export tcp-diag metrics per connection or aggregated per remote address
+    #[arg(long, value_enum, default_value = "connection")]
+    tcp_diag_by: TcpDiagBy,
 }
 
 #[derive(Clone)]
@@ -76,13 +151,65 @@ async fn main() -> anyhow::Result<()> {
     for kind in enabled {
         match kind {
             MonitorKind::Sched => {
-                monitors.push(Box::new(ProcessSchedMonitor::new(&registry, cli.proc_name.clone())?));
+                let labels = ProcLabels {
+                    cgroup: cli.proc_cgroup_labels,
+                };
+                let mut sched = ProcessSchedMonitor::new(&registry, cli.proc_name.clone(), labels)?;
+                for stat in &cli.proc_stats {
+                    match stat {
+                        ProcStatKind::Io => sched.add_collector(Box::new(ProcIoCollector::new(&registry, labels)?)),
+                        ProcStatKind::Smaps => {
+                            sched.add_collector(Box::new(ProcSmapsCollector::new(&registry, labels)?))
+                        }
+                        ProcStatKind::Numa => sched.add_collector(Box::new(ProcNumaCollector::new(&registry, labels)?)),
+                        ProcStatKind::Fd => sched.add_collector(Box::new(ProcFdCollector::new(&registry, labels)?)),
+                        ProcStatKind::Limits => {
+                            sched.add_collector(Box::new(ProcLimitsCollector::new(&registry, labels)?))
+                        }
+                        ProcStatKind::Perf => sched.add_collector(Box::new(ProcPerfCollector::new(&registry, labels)?)),
+                        ProcStatKind::Sockets => {
+                            sched.add_collector(Box::new(ProcSocketsCollector::new(&registry, labels)?))
+                        }
+                    }
+                }
+                if let Some(path) = &cli.sched_policy {
+                    let policy = ProcPolicyCollector::new(&registry, labels, path, &cli.proc_name)?;
+                    sched.add_collector(Box::new(policy));
+                }
+                sched.set_revalidate_ticks(cli.proc_revalidate_ticks);
+                if cli.proc_descendants {
+                    sched.include_descendants();
+                }
+                if cli.sample_hz > 0 {
+                    ThreadSampler::spawn(&registry, labels, sched.matched_handle(), cli.sample_hz)?;
+                }
+                if cli.proc_events {
+                    if let Err(e) = sched.enable_events(cli.proc_rescan_ticks) {
+                        warn!("sched: proc connector unavailable, scanning /proc every tick: {e:#}");
+                    }
+                }
+                monitors.push(Box::new(sched));
+            }
+            MonitorKind::ProcTop => {
+                monitors.push(Box::new(TopProcessesMonitor::new(&registry, cli.top_n, cli.top_by)?));
+            }
+            MonitorKind::KThreads => {
+                monitors.push(Box::new(KernelThreadsMonitor::new(&registry)?));
             }
             MonitorKind::Snmp => {
-                monitors.push(Box::new(SNMPMonitor::new(&registry)?));
+                monitors.push(Box::new(SNMPMonitor::new(&registry, cli.snmp_protos.clone())?));
             }
-            MonitorKind::NetDev => {
-                monitors.push(Box::new(NetSysfsStatsMonitor::new(&registry)?));
+            MonitorKind::NetDev => match cli.netdev_backend {
+                NetDevBackend::Sysfs => monitors.push(Box::new(NetSysfsStatsMonitor::new(&registry)?)),
+                NetDevBackend::Netlink => {
+                    monitors.push(Box::new(NetlinkStatsMonitor::new(&registry, cli.netdev_kinds.clone())?))
+                }
+            },
+            MonitorKind::NetDevInfo => {
+                monitors.push(Box::new(NetDevInfoMonitor::new(&registry)?));
+            }
+            MonitorKind::Ethtool => {
+                monitors.push(Box::new(EthtoolMonitor::new(&registry, cli.ethtool_ifaces.clone())?));
             }
             MonitorKind::DiskStat => {
                 monitors.push(Box::new(DiskStatsMonitor::new(&registry)?));
@@ -99,6 +226,26 @@ async fn main() -> anyhow::Result<()> {
             MonitorKind::SoftIrqs => {
                 monitors.push(Box::new(SoftirqsMonitor::new(&registry)?));
             }
+            MonitorKind::Snmp6 => {
+                monitors.push(Box::new(Snmp6Monitor::new(&registry)?));
+            }
+            MonitorKind::NetStat => {
+                monitors.push(Box::new(NetstatMonitor::new(&registry, cli.netstat_keys.clone())?));
+            }
+            MonitorKind::SockStat => {
+                monitors.push(Box::new(SockstatMonitor::new(&registry)?));
+            }
+            MonitorKind::SocketTable => {
+                monitors.push(Box::new(SocketTableMonitor::new(&registry, cli.socket_ports.clone())?));
+            }
+            MonitorKind::TcpDiag => {
+                monitors.push(Box::new(TcpDiagMonitor::new(
+                    &registry,
+                    cli.tcp_diag_sports.clone(),
+                    cli.tcp_diag_dports.clone(),
+                    cli.tcp_diag_by,
+                )?));
+            }
             MonitorKind::SoftNetStat => {
                 monitors.push(Box::new(SoftnetStatMonitor::new(&registry)?));
             }
@@ -2,8 +2,17 @@ use clap::ValueEnum;
 #[derive(Debug, Clone, ValueEnum)]
 pub enum MonitorKind {
     Sched,
+    KThreads,
+    ProcTop,
     Snmp,
+    Snmp6,
+    NetStat,
+    SockStat,
+    SocketTable,
+    TcpDiag,
     NetDev,
+    NetDevInfo,
+    Ethtool,
     NetDevQueues,
     DiskStat,
     Interrupts,
@@ -12,6 +21,78 @@ pub enum MonitorKind {
     SoftNetStat,
 }
 
+/// Additional per-process files read for PIDs matched by the sched monitor.
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ProcStatKind {
+    Io,
+    Smaps,
+    Numa,
+    Fd,
+    Limits,
+    Perf,
+    Sockets,
+}
+
+/// Sections of /proc/net/snmp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SnmpProto {
+    Ip,
+    Icmp,
+    IcmpMsg,
+    Tcp,
+    Udp,
+    UdpLite,
+}
+
+impl SnmpProto {
+    /// Parses a section name as it appears in /proc/net/snmp, e.g. "IcmpMsg".
+    pub fn from_section(section: &str) -> Option<Self> {
+        Some(match section {
+            "Ip" => Self::Ip,
+            "Icmp" => Self::Icmp,
+            "IcmpMsg" => Self::IcmpMsg,
+            "Tcp" => Self::Tcp,
+            "Udp" => Self::Udp,
+            "UdpLite" => Self::UdpLite,
+            _ => return None,
+        })
+    }
+
+    /// Value of the `proto` label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Icmp => "icmp",
+            Self::IcmpMsg => "icmpmsg",
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+            Self::UdpLite => "udplite",
+        }
+    }
+}
+
+/// Counter the host-wide top-N process monitor ranks by.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TopBy {
+    Cpu,
+    Involuntary,
+    Migrations,
+}
+
+/// Source of the per-interface counters of the netdev monitor.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum NetDevBackend {
+    Sysfs,
+    Netlink,
+}
+
+/// How the tcp-diag monitor groups the selected connections.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TcpDiagBy {
+    Connection,
+    Peer,
+}
+
 #[allow(dead_code)]
 pub trait Monitor {
     fn collect(&mut self) -> anyhow::Result<()>;
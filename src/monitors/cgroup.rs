@@ -0,0 +1,118 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+// Attribution of a process to its cgroup, container and systemd unit from /proc/<pid>/cgroup.
+// Container IDs are recognised in the path layouts of
+//   docker:     /docker/<id>, /system.slice/docker-<id>.scope
+//   containerd: /kubepods/.../<id>, .../cri-containerd-<id>.scope
+//   CRI-O:      .../crio-<id>.scope
+//   podman:     .../libpod-<id>.scope, /machine.slice/libpod-<id>.scope/container
+#[derive(Debug, Clone, Default)]
+pub struct CgroupInfo {
+    pub path: String,
+    pub container_id: String,
+    pub unit: String,
+}
+
+const CONTAINER_PREFIXES: &[&str] = &["docker-", "cri-containerd-", "crio-", "libpod-"];
+
+impl CgroupInfo {
+    pub fn read(pid: u32) -> Result<Self> {
+        let path = format!("/proc/{pid}/cgroup");
+        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+        Ok(Self::from_path(Self::pick_path(&content)))
+    }
+
+    /// Prefers the unified (v2) hierarchy, then the systemd v1 hierarchy, then the first entry.
+    fn pick_path(content: &str) -> &str {
+        // e.g. "0::/system.slice/docker-<id>.scope" or "1:name=systemd:/user.slice/..."
+        let entries: Vec<(&str, &str)> = content
+            .lines()
+            .filter_map(|l| {
+                let mut parts = l.splitn(3, ':');
+                let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+                Some((controllers, path))
+            })
+            .collect();
+
+        entries
+            .iter()
+            .find(|(c, _)| c.is_empty())
+            .or_else(|| entries.iter().find(|(c, _)| *c == "name=systemd"))
+            .or_else(|| entries.first())
+            .map_or("", |(_, p)| p)
+    }
+
+    fn from_path(path: &str) -> Self {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let container_id = segments
+            .iter()
+            .rev()
+            .find_map(|seg| Self::container_id(seg))
+            .unwrap_or_default();
+        let unit = segments
+            .iter()
+            .rev()
+            .find(|seg| seg.ends_with(".service") || seg.ends_with(".scope"))
+            .map_or_else(String::new, |s| s.to_string());
+
+        Self {
+            path: path.to_string(),
+            container_id,
+            unit,
+        }
+    }
+
+    fn container_id(segment: &str) -> Option<String> {
+        let s = segment.strip_suffix(".scope").unwrap_or(segment);
+        let s = CONTAINER_PREFIXES.iter().find_map(|p| s.strip_prefix(p)).unwrap_or(s);
+        let is_id = s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit());
+        is_id.then(|| s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4f1c2b7e9a0d3c5b8e6f1a2d4c7b9e0f3a5d8c1b6e9f2a4d7c0b3e5f8a1d6c9b";
+
+    fn info(path: &str) -> (String, String) {
+        let i = CgroupInfo::from_path(path);
+        (i.container_id, i.unit)
+    }
+
+    #[test]
+    fn from_path_layouts() {
+        assert_eq!(
+            info(&format!("/system.slice/docker-{ID}.scope")),
+            (ID.to_string(), format!("docker-{ID}.scope"))
+        );
+        assert_eq!(info(&format!("/docker/{ID}")), (ID.to_string(), String::new()));
+        assert_eq!(
+            info(&format!(
+                "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1234.slice/cri-containerd-{ID}.scope"
+            )),
+            (ID.to_string(), format!("cri-containerd-{ID}.scope"))
+        );
+        assert_eq!(
+            info(&format!("/machine.slice/libpod-{ID}.scope/container")),
+            (ID.to_string(), format!("libpod-{ID}.scope"))
+        );
+        assert_eq!(
+            info("/system.slice/nginx.service"),
+            (String::new(), "nginx.service".to_string())
+        );
+        assert_eq!(info("/"), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn pick_path_prefers_unified_hierarchy() {
+        let v1 = "12:cpu,cpuacct:/system.slice/a.service\n1:name=systemd:/system.slice/b.service\n";
+        assert_eq!(CgroupInfo::pick_path(v1), "/system.slice/b.service");
+        let hybrid = format!("{v1}0::/system.slice/c.service\n");
+        assert_eq!(CgroupInfo::pick_path(&hybrid), "/system.slice/c.service");
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+
+// Driver statistics (`ethtool -S`) over the SIOCETHTOOL ioctl:
+//   ethtool_stat{iface, key, queue}
+// Per-queue names such as rx_queue_3_drops, rx3_packets or rx-3.bytes are split into
+// key="rx_queue_drops" / "rx_packets" / "rx_bytes" and queue="3"; other stats have queue="".
+// Layouts follow include/uapi/linux/ethtool.h.
+
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+const ETHTOOL_GSTRINGS: u32 = 0x1b;
+const ETHTOOL_GSTATS: u32 = 0x1d;
+const ETHTOOL_GSSET_INFO: u32 = 0x37;
+const ETH_SS_STATS: u32 = 1;
+const ETH_GSTRING_LEN: usize = 32;
+
+/// struct ifreq with ifr_data
+#[repr(C)]
+struct IfReq {
+    name: [u8; libc::IFNAMSIZ],
+    data: *mut libc::c_void,
+    _pad: [u8; 16],
+}
+
+struct IfaceStats {
+    // (key, queue) per stat index, from ETHTOOL_GSTRINGS
+    names: Vec<(String, String)>,
+}
+
+pub struct EthtoolMonitor {
+    sock: OwnedFd,
+    root: PathBuf,
+    // empty selects every interface but lo
+    ifaces: Vec<String>,
+    stats: GaugeVec,
+    known: HashMap<String, IfaceStats>,
+}
+
+impl EthtoolMonitor {
+    pub fn new(registry: &Registry, ifaces: Vec<String>) -> Result<Self> {
+        // SAFETY: plain socket(2) call, the returned fd is owned below
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("socket for SIOCETHTOOL");
+        }
+        // SAFETY: fd is a freshly created, valid socket
+        let sock = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let stats = GaugeVec::new(
+            Opts::new("ethtool_stat", "Driver statistics from the ethtool ioctl (ethtool -S)"),
+            &["iface", "key", "queue"],
+        )?;
+        registry.register(Box::new(stats.clone()))?;
+
+        Ok(Self {
+            sock,
+            root: PathBuf::from("/sys/class/net"),
+            ifaces,
+            stats,
+            known: HashMap::new(),
+        })
+    }
+
+    /// Issues an ethtool command whose struct is `buf` (starting with the u32 cmd).
+    fn ioctl(&self, iface: &str, buf: &mut [u8]) -> io::Result<()> {
+        let mut req = IfReq {
+            name: [0; libc::IFNAMSIZ],
+            data: buf.as_mut_ptr() as *mut libc::c_void,
+            _pad: [0; 16],
+        };
+        let n = iface.len().min(libc::IFNAMSIZ - 1);
+        req.name[..n].copy_from_slice(&iface.as_bytes()[..n]);
+
+        // SAFETY: req is a valid ifreq whose data points to a buffer sized for the command
+        let rc = unsafe { libc::ioctl(self.sock.as_raw_fd(), SIOCETHTOOL as _, &mut req) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn stats_count(&self, iface: &str) -> io::Result<usize> {
+        // struct ethtool_sset_info: cmd, reserved, sset_mask (u64), data[]
+        let mut buf = vec![0u8; 20];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSSET_INFO.to_ne_bytes());
+        buf[8..16].copy_from_slice(&(1u64 << ETH_SS_STATS).to_ne_bytes());
+        self.ioctl(iface, &mut buf)?;
+        // the kernel clears the bits of unsupported sets
+        if u64::from_ne_bytes(buf[8..16].try_into().unwrap()) == 0 {
+            return Ok(0);
+        }
+        Ok(u32::from_ne_bytes(buf[16..20].try_into().unwrap()) as usize)
+    }
+
+    fn stat_names(&self, iface: &str, n: usize) -> io::Result<Vec<(String, String)>> {
+        // struct ethtool_gstrings: cmd, string_set, len, data[len * ETH_GSTRING_LEN]
+        // the kernel ignores len and writes as many strings as the driver has now
+        let cap = headroom(n);
+        let mut buf = vec![0u8; 12 + cap * ETH_GSTRING_LEN];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSTRINGS.to_ne_bytes());
+        buf[4..8].copy_from_slice(&ETH_SS_STATS.to_ne_bytes());
+        buf[8..12].copy_from_slice(&(n as u32).to_ne_bytes());
+        self.ioctl(iface, &mut buf)?;
+
+        let len = u32::from_ne_bytes(buf[8..12].try_into().unwrap()) as usize;
+        if len > cap {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{len} stat strings, expected {n}"),
+            ));
+        }
+        Ok(buf[12..12 + len * ETH_GSTRING_LEN]
+            .chunks_exact(ETH_GSTRING_LEN)
+            .map(|s| {
+                let end = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+                split_queue(&String::from_utf8_lossy(&s[..end]))
+            })
+            .collect())
+    }
+
+    fn stat_values(&self, iface: &str, n: usize) -> io::Result<Vec<u64>> {
+        // struct ethtool_stats: cmd, n_stats, data[n_stats] (u64); n_stats is overwritten too
+        let cap = headroom(n);
+        let mut buf = vec![0u8; 8 + cap * 8];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSTATS.to_ne_bytes());
+        buf[4..8].copy_from_slice(&(n as u32).to_ne_bytes());
+        self.ioctl(iface, &mut buf)?;
+
+        let len = (u32::from_ne_bytes(buf[4..8].try_into().unwrap()) as usize).min(cap);
+        Ok(buf[8..8 + len * 8]
+            .chunks_exact(8)
+            .map(|v| u64::from_ne_bytes(v.try_into().unwrap()))
+            .collect())
+    }
+
+    fn collect_iface(&mut self, iface: &str) -> io::Result<()> {
+        let mut n = self.stats_count(iface)?;
+        if n == 0 {
+            self.forget(iface);
+            return Ok(());
+        }
+
+        // the string set changes with the number of queues, e.g. after ethtool -L, possibly
+        // between two of our calls; retry once with the count the kernel last reported
+        let mut values = None;
+        for _ in 0..2 {
+            if self.known.get(iface).map(|k| k.names.len()) != Some(n) {
+                let names = self.stat_names(iface, n)?;
+                self.forget(iface);
+                n = names.len();
+                self.known.insert(iface.to_string(), IfaceStats { names });
+            }
+            let v = self.stat_values(iface, n)?;
+            if v.len() == n {
+                values = Some(v);
+                break;
+            }
+            n = v.len();
+        }
+        let Some(values) = values else {
+            self.forget(iface);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stat count kept changing, skipping this tick",
+            ));
+        };
+
+        let names = &self.known[iface].names;
+        for ((key, queue), v) in names.iter().zip(values) {
+            self.stats
+                .with_label_values(&[iface, key.as_str(), queue.as_str()])
+                .set(v as f64);
+        }
+        Ok(())
+    }
+
+    fn forget(&mut self, iface: &str) {
+        if let Some(old) = self.known.remove(iface) {
+            for (key, queue) in &old.names {
+                let _ = self.stats.remove_label_values(&[iface, key.as_str(), queue.as_str()]);
+            }
+        }
+    }
+}
+
+/// Entries to allocate for a string set or stats array the kernel said has `n` entries, as it
+/// may have grown by the time the ioctl copies it out.
+fn headroom(n: usize) -> usize {
+    n * 2 + 64
+}
+
+/// Splits a queue number out of a stat name: "rx_queue_3_drops" -> ("rx_queue_drops", "3"),
+/// "tx3_bytes" -> ("tx_bytes", "3"), "rx-3.packets" -> ("rx_packets", "3").
+/// Names without exactly one such number (e.g. "rx_65_to_127_bytes") are kept whole.
+fn split_queue(name: &str) -> (String, String) {
+    let tokens: Vec<&str> = name.split(['_', '.']).collect();
+    let mut found = None;
+    for (i, t) in tokens.iter().enumerate() {
+        let candidate = if t.bytes().all(|c| c.is_ascii_digit()) && !t.is_empty() {
+            // standalone number after a queue word: queue_3, q_3, ch_3
+            matches!(
+                i.checked_sub(1).map(|p| tokens[p]),
+                Some("queue" | "q" | "ch" | "channel")
+            )
+            .then_some((None, *t))
+        } else {
+            // number fused onto a direction or queue prefix: rx3, txq3, ch3, rx-3
+            let digits = t.len() - t.bytes().rev().take_while(u8::is_ascii_digit).count();
+            let (prefix, num) = t.split_at(digits);
+            let prefix = prefix.strip_suffix('-').unwrap_or(prefix);
+            (!num.is_empty() && matches!(prefix, "rx" | "tx" | "rxq" | "txq" | "q" | "ch"))
+                .then_some((Some(prefix), num))
+        };
+        if let Some((prefix, num)) = candidate {
+            if found.is_some() {
+                return (name.to_string(), String::new());
+            }
+            found = Some((i, prefix, num));
+        }
+    }
+
+    let Some((i, prefix, num)) = found else {
+        return (name.to_string(), String::new());
+    };
+    let key: Vec<&str> = tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(j, t)| match (j == i, prefix) {
+            (false, _) => Some(*t),
+            (true, Some(p)) => Some(p),
+            (true, None) => None,
+        })
+        .collect();
+    (key.join("_"), num.to_string())
+}
+
+impl Monitor for EthtoolMonitor {
+    fn name(&self) -> &'static &str {
+        &"ethtool"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let ifaces: Vec<String> = if self.ifaces.is_empty() {
+            fs::read_dir(&self.root)
+                .with_context(|| format!("reading {:?}", self.root))?
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|i| i != "lo")
+                .collect()
+        } else {
+            self.ifaces.clone()
+        };
+
+        for iface in &ifaces {
+            // many virtual interfaces have no driver stats (EOPNOTSUPP), or went away (ENODEV)
+            if let Err(e) = self.collect_iface(iface) {
+                debug!("ethtool: {iface}: {e}");
+                self.forget(iface);
+            }
+        }
+
+        let gone: Vec<String> = self.known.keys().filter(|i| !ifaces.contains(i)).cloned().collect();
+        for iface in gone {
+            self.forget(&iface);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_queue_names() {
+        assert_eq!(split_queue("rx_queue_3_drops"), ("rx_queue_drops".into(), "3".into()));
+        assert_eq!(split_queue("rx-3.packets"), ("rx_packets".into(), "3".into()));
+        assert_eq!(split_queue("tx3_bytes"), ("tx_bytes".into(), "3".into()));
+        assert_eq!(
+            split_queue("rx_65_to_127_bytes"),
+            ("rx_65_to_127_bytes".into(), String::new())
+        );
+        assert_eq!(split_queue("rx_packets"), ("rx_packets".into(), String::new()));
+    }
+}
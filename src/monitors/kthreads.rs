@@ -0,0 +1,269 @@
+use std::{collections::HashSet, fs};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+use crate::monitors::proc::{is_pid_gone, read_comm, read_stat, ProcessSchedMonitor, SCHED_KEYS};
+
+// Kernel-thread mode of the sched monitor: discovers per-CPU softirq/NAPI/workqueue
+// threads by name and exposes their /proc/<pid>/sched and /proc/<pid>/schedstat as:
+//   kthread_sched{kind, thread, pid, cpu, iface, key="nr_switches|..."} <value>
+//   kthread_schedstat{kind, thread, pid, cpu, iface, key="run_time_ns|wait_time_ns|timeslices"} <value>
+// so RX softirq load can be joined with softnet_stat{cpu} per CPU. pid keeps threads apart whose
+// other labels are equal, e.g. all unbound NAPI threads of a NIC whose names were cut the same.
+//
+// Thread names:
+//   ksoftirqd/<cpu>
+//   napi/<iface>-<napi_id>   (threaded NAPI; cpu is its affinity when pinned to one CPU; comm
+//                            is cut at 15 bytes, so iface is matched against /sys/class/net
+//                            and left empty when the cut name fits none or several)
+//   kworker/<cpu>:<id>[H]    (kworker/u<pool>:<id> and kworker/R-<wq> rescuers are unbound, cpu="")
+
+const KTHREADD_PID: u32 = 2;
+
+const SCHEDSTAT_KEYS: &[&str] = &["run_time_ns", "wait_time_ns", "timeslices"];
+
+pub struct KernelThreadsMonitor {
+    sched: GaugeVec,
+    schedstat: GaugeVec,
+    // label sets written on the previous tick, to drop threads that went away
+    seen: HashSet<[String; 5]>,
+}
+
+#[derive(Debug)]
+struct KThread {
+    kind: &'static str,
+    // comm without the kworker "-<workqueue>" suffix, which changes with the work item
+    thread: String,
+    cpu: String,
+    iface: String,
+}
+
+impl KernelThreadsMonitor {
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let labels = &["kind", "thread", "pid", "cpu", "iface", "key"];
+
+        let sched = GaugeVec::new(
+            Opts::new(
+                "kthread_sched",
+                "Values from /proc/<pid>/sched of per-CPU kernel threads",
+            ),
+            labels,
+        )?;
+        registry.register(Box::new(sched.clone()))?;
+
+        let schedstat = GaugeVec::new(
+            Opts::new(
+                "kthread_schedstat",
+                "Values from /proc/<pid>/schedstat of per-CPU kernel threads",
+            ),
+            labels,
+        )?;
+        registry.register(Box::new(schedstat.clone()))?;
+
+        Ok(Self {
+            sched,
+            schedstat,
+            seen: HashSet::new(),
+        })
+    }
+
+    fn classify(pid: u32, comm: &str, ifaces: &[String]) -> Option<KThread> {
+        if let Some(cpu) = comm.strip_prefix("ksoftirqd/") {
+            return Some(KThread {
+                kind: "ksoftirqd",
+                thread: comm.to_string(),
+                cpu: cpu.to_string(),
+                iface: String::new(),
+            });
+        }
+
+        if let Some(rest) = comm.strip_prefix("napi/") {
+            return Some(KThread {
+                kind: "napi",
+                thread: comm.to_string(),
+                cpu: Self::pinned_cpu(pid).unwrap_or_default(),
+                iface: napi_iface(rest, ifaces).unwrap_or_default().to_string(),
+            });
+        }
+
+        if let Some(rest) = comm.strip_prefix("kworker/") {
+            // rescuers keep their fixed workqueue name, e.g. "kworker/R-mm_percpu_wq"
+            let pool = match rest.split_once('-') {
+                Some(("R", _)) => rest,
+                Some((p, _)) => p,
+                None => rest,
+            };
+            let cpu = match pool.split_once(':') {
+                Some((cpu, _)) if !cpu.starts_with('u') => cpu.to_string(),
+                _ => String::new(),
+            };
+            return Some(KThread {
+                kind: "kworker",
+                thread: format!("kworker/{pool}"),
+                cpu,
+                iface: String::new(),
+            });
+        }
+
+        None
+    }
+
+    /// CPU from Cpus_allowed_list in /proc/<pid>/status, if the thread is pinned to exactly one.
+    fn pinned_cpu(pid: u32) -> Option<String> {
+        let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+        let list = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Cpus_allowed_list:"))?
+            .trim();
+        list.parse::<u32>().ok().map(|c| c.to_string())
+    }
+
+    fn read_schedstat(pid: u32) -> Result<Vec<u64>> {
+        // "run_time_ns wait_time_ns timeslices"
+        let path = format!("/proc/{pid}/schedstat");
+        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+        content
+            .split_whitespace()
+            .map(|v| v.parse::<u64>().with_context(|| format!("parsing {path}")))
+            .collect()
+    }
+
+    fn collect_thread(&self, pid: u32, kt: &KThread, seen: &mut HashSet<[String; 5]>) -> Result<()> {
+        let s = ProcessSchedMonitor::read_sched(pid)?;
+        let stat = Self::read_schedstat(pid)?;
+
+        let base = kt.labels(pid);
+        let with_key = |key: &'static str| -> [&str; 6] {
+            let [kind, thread, pid, cpu, iface] = base.each_ref().map(String::as_str);
+            [kind, thread, pid, cpu, iface, key]
+        };
+
+        for (key, v) in SCHED_KEYS.iter().zip(s.values()) {
+            self.sched.with_label_values(&with_key(key)).set(v);
+        }
+        for (key, v) in SCHEDSTAT_KEYS.iter().zip(stat) {
+            self.schedstat.with_label_values(&with_key(key)).set(v as f64);
+        }
+
+        seen.insert(base);
+        Ok(())
+    }
+}
+
+impl KThread {
+    /// Label values without the key: kind, thread, pid, cpu, iface.
+    fn labels(&self, pid: u32) -> [String; 5] {
+        [
+            self.kind.to_string(),
+            self.thread.clone(),
+            pid.to_string(),
+            self.cpu.clone(),
+            self.iface.clone(),
+        ]
+    }
+}
+
+/// Interface of a threaded NAPI poller from the "<iface>-<napi_id>" part of its comm, which
+/// may be truncated anywhere: the longest interface it starts with, or the only one it is a
+/// prefix of.
+fn napi_iface<'a>(rest: &str, ifaces: &'a [String]) -> Option<&'a str> {
+    let full = ifaces
+        .iter()
+        .filter(|i| rest.strip_prefix(i.as_str()).is_some_and(|r| r.starts_with('-')))
+        .max_by_key(|i| i.len());
+    if let Some(iface) = full {
+        return Some(iface);
+    }
+    let mut cut = ifaces.iter().filter(|i| format!("{i}-").starts_with(rest));
+    match (cut.next(), cut.next()) {
+        (Some(iface), None) => Some(iface),
+        _ => None,
+    }
+}
+
+impl Monitor for KernelThreadsMonitor {
+    fn name(&self) -> &'static &str {
+        &"kthreads"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let mut seen = HashSet::new();
+        let ifaces: Vec<String> = fs::read_dir("/sys/class/net")
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for entry in fs::read_dir("/proc").context("reading /proc")?.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            let Ok(comm) = read_comm(pid) else {
+                continue;
+            };
+            let Some(kt) = Self::classify(pid, &comm, &ifaces) else {
+                continue;
+            };
+            // a user process could be named like a kernel thread
+            match read_stat(pid) {
+                Ok(st) if st.ppid == KTHREADD_PID => {}
+                _ => continue,
+            }
+
+            if let Err(e) = self.collect_thread(pid, &kt, &mut seen) {
+                if !is_pid_gone(&e) {
+                    debug!("kthreads: pid {pid} ({}): {e:#}", kt.thread);
+                }
+            }
+        }
+
+        for gone in self.seen.difference(&seen) {
+            for (vec, keys) in [(&self.sched, SCHED_KEYS), (&self.schedstat, SCHEDSTAT_KEYS)] {
+                for key in keys {
+                    let mut labels: Vec<&str> = gone.iter().map(String::as_str).collect();
+                    labels.push(key);
+                    let _ = vec.remove_label_values(&labels);
+                }
+            }
+        }
+
+        debug!("kthreads: updated {} kernel threads", seen.len());
+        self.seen = seen;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn napi_iface_handles_truncated_and_dashed_names() {
+        let ifaces = ["enp175s0f0", "enp175s0f1", "br-lan", "eth0"].map(str::to_string);
+        // comm "napi/enp175s0f0" is cut before the napi id
+        assert_eq!(napi_iface("enp175s0f0", &ifaces), Some("enp175s0f0"));
+        assert_eq!(napi_iface("eth0-8193", &ifaces), Some("eth0"));
+        assert_eq!(napi_iface("br-lan-8193", &ifaces), Some("br-lan"));
+        // cut inside the name, matching two interfaces
+        assert_eq!(napi_iface("enp175s0f", &ifaces), None);
+        assert_eq!(napi_iface("wlan0-8193", &ifaces), None);
+    }
+
+    #[test]
+    fn napi_threads_with_the_same_cut_name_get_separate_series() {
+        let ifaces = ["enp175s0f0".to_string()];
+        // two unbound pollers of one NIC, both named "napi/enp175s0f0" after truncation;
+        // classify finds no pinned CPU for PIDs that do not exist
+        let a = KernelThreadsMonitor::classify(u32::MAX - 1, "napi/enp175s0f0", &ifaces).unwrap();
+        let b = KernelThreadsMonitor::classify(u32::MAX - 2, "napi/enp175s0f0", &ifaces).unwrap();
+        assert_eq!((a.cpu.as_str(), a.iface.as_str()), ("", "enp175s0f0"));
+
+        let labels: HashSet<[String; 5]> = [a.labels(u32::MAX - 1), b.labels(u32::MAX - 2)].into();
+        assert_eq!(labels.len(), 2);
+    }
+}
@@ -1,9 +1,29 @@
+pub mod cgroup;
 pub mod diskstat;
+pub mod ethtool;
 pub mod interrupts;
+pub mod kthreads;
 pub mod memstat;
+pub mod netdev_info;
+pub mod netdev_link;
 pub mod netdev_stat;
+pub mod netlink;
+pub mod netstat;
 pub mod proc;
+pub mod proc_events;
+pub mod proc_fd;
+pub mod proc_io;
+pub mod proc_mem;
+pub mod proc_perf;
+pub mod proc_policy;
+pub mod proc_sampler;
+pub mod proc_sock;
+pub mod proc_top;
 pub mod queues;
 pub mod snmp;
+pub mod snmp6;
+pub mod sockstat;
+pub mod socktable;
 pub mod softirqs;
 pub mod softnet_stat;
+pub mod tcp_diag;
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+use crate::monitors::netlink::{link_kinds, NetlinkSocket, NO_LINK_KIND};
+
+// Interface metadata from the sysfs attributes next to /sys/class/net/<iface>/statistics:
+//   netdev_info{iface, operstate, duplex, driver, address, kind}  1
+//   netdev_attr{iface, key="speed_mbps|mtu|tx_queue_len|carrier|carrier_changes|
+//                          carrier_up_count|carrier_down_count|rx_queues|tx_queues"}
+// kind is IFLA_INFO_KIND from an RTM_GETLINK dump (bridge, bond, vlan, veth, tun, ...) or
+// "device" for links without one, the same vocabulary as netdev_stat{kind}.
+// speed is omitted while the kernel reports none (link down, virtual interfaces).
+
+const ATTRS: &[(&str, &str)] = &[
+    ("speed_mbps", "speed"),
+    ("mtu", "mtu"),
+    ("tx_queue_len", "tx_queue_len"),
+    ("carrier", "carrier"),
+    ("carrier_changes", "carrier_changes"),
+    ("carrier_up_count", "carrier_up_count"),
+    ("carrier_down_count", "carrier_down_count"),
+];
+const QUEUE_KEYS: &[(&str, &str)] = &[("rx_queues", "rx-"), ("tx_queues", "tx-")];
+
+struct IfaceState {
+    // label values of the current netdev_info series
+    info: [String; 6],
+    carrier_changes: Option<u64>,
+}
+
+pub struct NetDevInfoMonitor {
+    root: PathBuf,
+    sock: NetlinkSocket,
+    info: GaugeVec,
+    attrs: GaugeVec,
+    include_lo: bool,
+    ifaces: HashMap<String, IfaceState>,
+}
+
+impl NetDevInfoMonitor {
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let info = GaugeVec::new(
+            Opts::new(
+                "netdev_info",
+                "Interface metadata from /sys/class/net/<iface> (value is always 1)",
+            ),
+            &["iface", "operstate", "duplex", "driver", "address", "kind"],
+        )?;
+        registry.register(Box::new(info.clone()))?;
+
+        let attrs = GaugeVec::new(
+            Opts::new(
+                "netdev_attr",
+                "Numeric interface attributes from /sys/class/net/<iface> (speed, MTU, carrier changes, queues)",
+            ),
+            &["iface", "key"],
+        )?;
+        registry.register(Box::new(attrs.clone()))?;
+
+        Ok(Self {
+            root: PathBuf::from("/sys/class/net"),
+            sock: NetlinkSocket::open(libc::NETLINK_ROUTE)?,
+            info,
+            attrs,
+            include_lo: false,
+            ifaces: HashMap::new(),
+        })
+    }
+
+    /// Trimmed attribute, or `None` if it cannot be read (e.g. speed of a down link is EINVAL).
+    fn read_attr(dir: &Path, name: &str) -> Option<String> {
+        fs::read_to_string(dir.join(name)).ok().map(|s| s.trim().to_string())
+    }
+
+    fn driver(dir: &Path) -> String {
+        fs::read_link(dir.join("device/driver"))
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .unwrap_or_default()
+    }
+
+    fn queue_count(dir: &Path, prefix: &str) -> Option<usize> {
+        let entries = fs::read_dir(dir.join("queues")).ok()?;
+        Some(
+            entries
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with(prefix))
+                .count(),
+        )
+    }
+
+    fn collect_iface(&mut self, iface: &str, dir: &Path, kind: String) {
+        let info = [
+            iface.to_string(),
+            Self::read_attr(dir, "operstate").unwrap_or_default(),
+            Self::read_attr(dir, "duplex").unwrap_or_default(),
+            Self::driver(dir),
+            Self::read_attr(dir, "address").unwrap_or_default(),
+            kind,
+        ];
+
+        let prev = self.ifaces.remove(iface);
+        if let Some(prev) = &prev {
+            if prev.info != info {
+                let _ = self.info.remove_label_values(&labels(&prev.info));
+            }
+        }
+        self.info.with_label_values(&labels(&info)).set(1.0);
+
+        let mut carrier_changes = None;
+        for (key, file) in ATTRS {
+            // speed is -1 for links without one
+            match Self::read_attr(dir, file).and_then(|v| v.parse::<i64>().ok()) {
+                Some(v) if v >= 0 => {
+                    self.attrs.with_label_values(&[iface, key]).set(v as f64);
+                    if *key == "carrier_changes" {
+                        carrier_changes = Some(v as u64);
+                    }
+                }
+                _ => {
+                    let _ = self.attrs.remove_label_values(&[iface, key]);
+                }
+            }
+        }
+        for (key, prefix) in QUEUE_KEYS {
+            if let Some(n) = Self::queue_count(dir, prefix) {
+                self.attrs.with_label_values(&[iface, key]).set(n as f64);
+            }
+        }
+
+        if let (Some(prev), Some(now)) = (prev.and_then(|p| p.carrier_changes), carrier_changes) {
+            if now > prev {
+                warn!(
+                    "netdev_info: {iface} carrier changed {} time(s), operstate now {}",
+                    now - prev,
+                    info[1]
+                );
+            }
+        }
+
+        self.ifaces
+            .insert(iface.to_string(), IfaceState { info, carrier_changes });
+    }
+}
+
+fn labels(values: &[String; 6]) -> [&str; 6] {
+    values.each_ref().map(String::as_str)
+}
+
+impl Monitor for NetDevInfoMonitor {
+    fn name(&self) -> &'static &str {
+        &"netdev_info"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let entries = fs::read_dir(&self.root).with_context(|| format!("reading {:?}", self.root))?;
+        let kinds = link_kinds(&mut self.sock)?;
+
+        let mut present = Vec::new();
+        for entry in entries {
+            let entry = entry.with_context(|| "iterating /sys/class/net entries".to_string())?;
+            let iface = entry.file_name().to_string_lossy().to_string();
+            if !self.include_lo && iface == "lo" {
+                continue;
+            }
+            // links created between the dump and read_dir have no kind yet
+            let kind = kinds.get(&iface).map_or(NO_LINK_KIND, String::as_str).to_string();
+            self.collect_iface(&iface, &entry.path(), kind);
+            present.push(iface);
+        }
+
+        // interfaces that were removed or renamed
+        let gone: Vec<String> = self.ifaces.keys().filter(|i| !present.contains(i)).cloned().collect();
+        for iface in gone {
+            let Some(state) = self.ifaces.remove(&iface) else {
+                continue;
+            };
+            let _ = self.info.remove_label_values(&labels(&state.info));
+            for (key, _) in ATTRS.iter().chain(QUEUE_KEYS) {
+                let _ = self.attrs.remove_label_values(&[iface.as_str(), key]);
+            }
+        }
+
+        debug!("netdev_info: updated {} interfaces", present.len());
+        Ok(())
+    }
+}
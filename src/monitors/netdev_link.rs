@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+use crate::monitors::netlink::{
+    attrs, c_str, link_kind, u64_at, NetlinkSocket, IFINFOMSG_LEN, IFLA_IFNAME, IFLA_LINKINFO, NO_LINK_KIND,
+    RTM_GETLINK, RTM_NEWLINK,
+};
+
+// Interface counters of every link (veth, bridge, bond, vlan, tun, ...) over rtnetlink:
+//   netdev_stat{iface, kind, key}          IFLA_STATS64 from one RTM_GETLINK dump; keys are the
+//                                          rtnl_link_stats64 fields, named like the sysfs statistics
+//   netdev_xstat{iface, kind, group, key}  IFLA_STATS_LINK_XSTATS(_SLAVE) from RTM_GETSTATS, for
+//                                          bridges and bonds and their ports
+// kind is IFLA_INFO_KIND, or "device" for links without one (physical NICs).
+// Layouts follow include/uapi/linux/if_link.h, if_bridge.h and if_bonding.h.
+
+const RTM_NEWSTATS: u16 = 92;
+const RTM_GETSTATS: u16 = 94;
+
+const IFLA_STATS64: u16 = 23;
+
+const IFLA_STATS_LINK_XSTATS: u16 = 2;
+const IFLA_STATS_LINK_XSTATS_SLAVE: u16 = 3;
+const LINK_XSTATS_TYPE_BRIDGE: u16 = 1;
+const LINK_XSTATS_TYPE_BOND: u16 = 2;
+const BRIDGE_XSTATS_MCAST: u16 = 2;
+const BRIDGE_XSTATS_STP: u16 = 4;
+const BOND_XSTATS_3AD: u16 = 1;
+
+// struct if_stats_msg: family, pad1, pad2, ifindex, filter_mask
+const IF_STATS_MSG_LEN: usize = 12;
+
+/// struct rtnl_link_stats64, in field order
+const STATS64_KEYS: &[&str] = &[
+    "rx_packets",
+    "tx_packets",
+    "rx_bytes",
+    "tx_bytes",
+    "rx_errors",
+    "tx_errors",
+    "rx_dropped",
+    "tx_dropped",
+    "multicast",
+    "collisions",
+    "rx_length_errors",
+    "rx_over_errors",
+    "rx_crc_errors",
+    "rx_frame_errors",
+    "rx_fifo_errors",
+    "rx_missed_errors",
+    "tx_aborted_errors",
+    "tx_carrier_errors",
+    "tx_fifo_errors",
+    "tx_heartbeat_errors",
+    "tx_window_errors",
+    "rx_compressed",
+    "tx_compressed",
+    "rx_nohandler",
+    "rx_otherhost_dropped",
+];
+
+/// struct br_mcast_stats, in field order; true for [rx, tx] pairs
+const BR_MCAST_KEYS: &[(&str, bool)] = &[
+    ("igmp_v1queries", true),
+    ("igmp_v2queries", true),
+    ("igmp_v3queries", true),
+    ("igmp_leaves", true),
+    ("igmp_v1reports", true),
+    ("igmp_v2reports", true),
+    ("igmp_v3reports", true),
+    ("igmp_parse_errors", false),
+    ("mld_v1queries", true),
+    ("mld_v2queries", true),
+    ("mld_leaves", true),
+    ("mld_v1reports", true),
+    ("mld_v2reports", true),
+    ("mld_parse_errors", false),
+    ("mcast_bytes", true),
+    ("mcast_packets", true),
+];
+
+/// struct bridge_stp_xstats, in field order
+const BR_STP_KEYS: &[&str] = &[
+    "transition_blk",
+    "transition_fwd",
+    "rx_bpdu",
+    "tx_bpdu",
+    "rx_tcn",
+    "tx_tcn",
+];
+
+/// BOND_3AD_STAT_* attribute types 0..
+const BOND_3AD_KEYS: &[&str] = &[
+    "lacpdu_rx",
+    "lacpdu_tx",
+    "lacpdu_unknown_rx",
+    "lacpdu_illegal_rx",
+    "marker_rx",
+    "marker_tx",
+    "marker_resp_rx",
+    "marker_resp_tx",
+    "marker_unknown_rx",
+];
+
+struct Link {
+    name: String,
+    kind: String,
+}
+
+pub struct NetlinkStatsMonitor {
+    sock: NetlinkSocket,
+    // empty includes every kind
+    kinds: Vec<String>,
+    include_lo: bool,
+    stats: GaugeVec,
+    xstats: GaugeVec,
+    // (iface, kind) and xstat label sets written on the previous tick
+    exported: HashSet<(String, String)>,
+    exported_x: HashSet<[String; 4]>,
+    // RTM_GETSTATS failed once already (kernels before 4.7, or the XSTATS filter is refused)
+    xstats_failed: bool,
+}
+
+impl NetlinkStatsMonitor {
+    pub fn new(registry: &Registry, kinds: Vec<String>) -> Result<Self> {
+        let sock = NetlinkSocket::open(libc::NETLINK_ROUTE)?;
+
+        let stats = GaugeVec::new(
+            Opts::new("netdev_stat", "Interface counters from rtnetlink IFLA_STATS64"),
+            &["iface", "kind", "key"],
+        )?;
+        registry.register(Box::new(stats.clone()))?;
+
+        let xstats = GaugeVec::new(
+            Opts::new(
+                "netdev_xstat",
+                "Link type specific counters from rtnetlink IFLA_STATS_LINK_XSTATS (bridge, bond)",
+            ),
+            &["iface", "kind", "group", "key"],
+        )?;
+        registry.register(Box::new(xstats.clone()))?;
+
+        Ok(Self {
+            sock,
+            kinds,
+            include_lo: false,
+            stats,
+            xstats,
+            exported: HashSet::new(),
+            exported_x: HashSet::new(),
+            xstats_failed: false,
+        })
+    }
+
+    /// One RTM_GETLINK dump: ifindex -> link, with IFLA_STATS64 exported on the way.
+    fn dump_links(&mut self, exported: &mut HashSet<(String, String)>) -> Result<HashMap<u32, Link>> {
+        let mut links = HashMap::new();
+        let mut req = vec![0u8; IFINFOMSG_LEN];
+        req[0] = libc::AF_UNSPEC as u8;
+
+        let (stats, kinds, include_lo) = (&self.stats, &self.kinds, self.include_lo);
+        self.sock
+            .dump(RTM_GETLINK, &req, |t, m| {
+                if t != RTM_NEWLINK || m.len() < IFINFOMSG_LEN {
+                    return;
+                }
+                let index = i32::from_ne_bytes([m[4], m[5], m[6], m[7]]) as u32;
+                let (mut name, mut kind, mut stats64) = (None, None, None);
+                for (at, payload) in attrs(&m[IFINFOMSG_LEN..]) {
+                    match at {
+                        IFLA_IFNAME => name = Some(c_str(payload)),
+                        IFLA_STATS64 => stats64 = Some(payload),
+                        IFLA_LINKINFO => kind = link_kind(payload),
+                        _ => {}
+                    }
+                }
+                let Some(name) = name else { return };
+                let kind = kind.unwrap_or_else(|| NO_LINK_KIND.to_string());
+                if (!include_lo && name == "lo") || (!kinds.is_empty() && !kinds.contains(&kind)) {
+                    return;
+                }
+
+                if let Some(s) = stats64 {
+                    // older kernels send fewer fields
+                    for (i, key) in STATS64_KEYS.iter().enumerate() {
+                        if let Some(v) = u64_at(s, i * 8) {
+                            stats
+                                .with_label_values(&[name.as_str(), kind.as_str(), key])
+                                .set(v as f64);
+                        }
+                    }
+                    exported.insert((name.clone(), kind.clone()));
+                }
+                links.insert(index, Link { name, kind });
+            })
+            .context("RTM_GETLINK dump")?;
+
+        Ok(links)
+    }
+
+    /// RTM_GETSTATS dump of the bridge/bond extended stats of the selected links.
+    fn dump_xstats(&mut self, links: &HashMap<u32, Link>, exported: &mut HashSet<[String; 4]>) -> Result<()> {
+        let mask: u32 = 1 << (IFLA_STATS_LINK_XSTATS - 1) | 1 << (IFLA_STATS_LINK_XSTATS_SLAVE - 1);
+        let mut req = vec![0u8; IF_STATS_MSG_LEN];
+        req[8..12].copy_from_slice(&mask.to_ne_bytes());
+
+        let xstats = &self.xstats;
+        self.sock
+            .dump(RTM_GETSTATS, &req, |t, m| {
+                if t != RTM_NEWSTATS || m.len() < IF_STATS_MSG_LEN {
+                    return;
+                }
+                let index = u32::from_ne_bytes([m[4], m[5], m[6], m[7]]);
+                let Some(link) = links.get(&index) else { return };
+
+                let mut set = |group: &str, key: &str, v: u64| {
+                    let labels = [link.name.clone(), link.kind.clone(), group.to_string(), key.to_string()];
+                    xstats
+                        .with_label_values(&labels.each_ref().map(String::as_str))
+                        .set(v as f64);
+                    exported.insert(labels);
+                };
+
+                for (at, nested) in attrs(&m[IF_STATS_MSG_LEN..]) {
+                    let port = match at {
+                        IFLA_STATS_LINK_XSTATS => "",
+                        IFLA_STATS_LINK_XSTATS_SLAVE => "port_",
+                        _ => continue,
+                    };
+                    for (xtype, payload) in attrs(nested) {
+                        match xtype {
+                            LINK_XSTATS_TYPE_BRIDGE => parse_bridge(payload, port, &mut set),
+                            LINK_XSTATS_TYPE_BOND => parse_bond(payload, port, &mut set),
+                            _ => {}
+                        }
+                    }
+                }
+            })
+            .context("RTM_GETSTATS dump")
+    }
+}
+
+fn parse_bridge(payload: &[u8], port: &str, set: &mut impl FnMut(&str, &str, u64)) {
+    for (t, s) in attrs(payload) {
+        match t {
+            BRIDGE_XSTATS_MCAST => {
+                let group = format!("bridge_{port}mcast");
+                let mut off = 0;
+                for (key, pair) in BR_MCAST_KEYS {
+                    if *pair {
+                        for dir in ["rx", "tx"] {
+                            if let Some(v) = u64_at(s, off) {
+                                set(&group, &format!("{key}_{dir}"), v);
+                            }
+                            off += 8;
+                        }
+                    } else {
+                        if let Some(v) = u64_at(s, off) {
+                            set(&group, key, v);
+                        }
+                        off += 8;
+                    }
+                }
+            }
+            BRIDGE_XSTATS_STP => {
+                let group = format!("bridge_{port}stp");
+                for (i, key) in BR_STP_KEYS.iter().enumerate() {
+                    if let Some(v) = u64_at(s, i * 8) {
+                        set(&group, key, v);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_bond(payload: &[u8], port: &str, set: &mut impl FnMut(&str, &str, u64)) {
+    let group = format!("bond_{port}3ad");
+    for (_, stats) in attrs(payload).filter(|(t, _)| *t == BOND_XSTATS_3AD) {
+        for (t, v) in attrs(stats) {
+            if let (Some(key), Some(v)) = (BOND_3AD_KEYS.get(t as usize), u64_at(v, 0)) {
+                set(&group, key, v);
+            }
+        }
+    }
+}
+
+impl Monitor for NetlinkStatsMonitor {
+    fn name(&self) -> &'static &str {
+        &"netdev_link"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let mut exported = HashSet::new();
+        let links = self.dump_links(&mut exported)?;
+
+        let mut exported_x = HashSet::new();
+        if let Err(e) = self.dump_xstats(&links, &mut exported_x) {
+            if !self.xstats_failed {
+                warn!("netdev_link: no extended link stats, exporting netdev_stat only: {e:#}");
+                self.xstats_failed = true;
+            } else {
+                debug!("netdev_link: {e:#}");
+            }
+        }
+
+        // links that were deleted or renamed
+        for (iface, kind) in self.exported.difference(&exported) {
+            for key in STATS64_KEYS {
+                let _ = self.stats.remove_label_values(&[iface.as_str(), kind.as_str(), key]);
+            }
+        }
+        for labels in self.exported_x.difference(&exported_x) {
+            let _ = self.xstats.remove_label_values(&labels.each_ref().map(String::as_str));
+        }
+
+        debug!("netdev_link: updated stats for {} interfaces", exported.len());
+        self.exported = exported;
+        self.exported_x = exported_x;
+        Ok(())
+    }
+}
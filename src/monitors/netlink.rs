@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use anyhow::{Context, Result};
+
+// Minimal request/dump helpers shared by the netlink-based monitors (sock_diag, rtnetlink,
+// and the message parsing of the proc connector).
+
+pub const NLMSG_HDRLEN: usize = 16;
+// attribute type bits, without NLA_F_NESTED / NLA_F_NET_BYTEORDER
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+pub const RTM_NEWLINK: u16 = 16;
+pub const RTM_GETLINK: u16 = 18;
+// struct ifinfomsg: family, pad, type, index, flags, change
+pub const IFINFOMSG_LEN: usize = 16;
+pub const IFLA_IFNAME: u16 = 3;
+pub const IFLA_LINKINFO: u16 = 18;
+const IFLA_INFO_KIND: u16 = 1;
+/// kind label of links without IFLA_INFO_KIND (physical NICs)
+pub const NO_LINK_KIND: &str = "device";
+
+pub struct NetlinkSocket {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl NetlinkSocket {
+    pub fn open(protocol: i32) -> Result<Self> {
+        // SAFETY: plain socket(2) call, the returned fd is owned below
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| format!("socket(AF_NETLINK, {protocol})"));
+        }
+        // SAFETY: fd is a freshly created, valid socket
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Self { fd, seq: 0 })
+    }
+
+    /// Sends a dump request of `msg_type` with `payload` (family header and attributes) and
+    /// calls `f(type, payload)` for every reply message until NLMSG_DONE.
+    pub fn dump(&mut self, msg_type: u16, payload: &[u8], mut f: impl FnMut(u16, &[u8])) -> Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        let total = NLMSG_HDRLEN + payload.len();
+        let mut msg = Vec::with_capacity(total);
+        msg.extend_from_slice(&(total as u32).to_ne_bytes());
+        msg.extend_from_slice(&msg_type.to_ne_bytes());
+        msg.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16).to_ne_bytes());
+        msg.extend_from_slice(&self.seq.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(payload);
+
+        // SAFETY: msg is a valid buffer of msg.len() bytes
+        let rc = unsafe { libc::send(self.fd.as_raw_fd(), msg.as_ptr() as *const libc::c_void, msg.len(), 0) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| format!("sending netlink request {msg_type}"));
+        }
+
+        let mut buf = vec![0u8; 32768];
+        loop {
+            // SAFETY: buf is a valid writable buffer of buf.len() bytes
+            let n = unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                return Err(e).with_context(|| format!("receiving netlink dump {msg_type}"));
+            }
+
+            let mut msg = &buf[..n as usize];
+            while msg.len() >= NLMSG_HDRLEN {
+                let len = u32_at(msg, 0) as usize;
+                if len < NLMSG_HDRLEN || len > msg.len() {
+                    break;
+                }
+                // replies left over from an earlier, interrupted dump
+                if u32_at(msg, 8) == self.seq {
+                    match u16_at(msg, 4) as i32 {
+                        libc::NLMSG_DONE => return Ok(()),
+                        libc::NLMSG_ERROR => {
+                            let errno = -(u32_at(msg, NLMSG_HDRLEN) as i32);
+                            return Err(io::Error::from_raw_os_error(errno))
+                                .with_context(|| format!("netlink dump {msg_type}"));
+                        }
+                        t => f(t as u16, &msg[NLMSG_HDRLEN..len]),
+                    }
+                }
+                msg = &msg[align(len).min(msg.len())..];
+            }
+        }
+    }
+}
+
+/// Iterates the (type, payload) attributes packed in `buf`.
+pub fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16_at(buf, 0) as usize;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let attr = (u16_at(buf, 2) & NLA_TYPE_MASK, &buf[4..len]);
+        buf = &buf[align(len).min(buf.len())..];
+        Some(attr)
+    })
+}
+
+/// IFLA_INFO_KIND nested in an IFLA_LINKINFO payload.
+pub fn link_kind(linkinfo: &[u8]) -> Option<String> {
+    attrs(linkinfo)
+        .find(|(t, _)| *t == IFLA_INFO_KIND)
+        .map(|(_, k)| c_str(k))
+}
+
+/// One RTM_GETLINK dump: interface name -> kind, as exported in the `kind` label of the
+/// netdev_* metrics.
+pub fn link_kinds(sock: &mut NetlinkSocket) -> Result<HashMap<String, String>> {
+    let mut kinds = HashMap::new();
+    let mut req = vec![0u8; IFINFOMSG_LEN];
+    req[0] = libc::AF_UNSPEC as u8;
+    sock.dump(RTM_GETLINK, &req, |t, m| {
+        if t != RTM_NEWLINK || m.len() < IFINFOMSG_LEN {
+            return;
+        }
+        let (mut name, mut kind) = (None, None);
+        for (at, payload) in attrs(&m[IFINFOMSG_LEN..]) {
+            match at {
+                IFLA_IFNAME => name = Some(c_str(payload)),
+                IFLA_LINKINFO => kind = link_kind(payload),
+                _ => {}
+            }
+        }
+        if let Some(name) = name {
+            kinds.insert(name, kind.unwrap_or_else(|| NO_LINK_KIND.to_string()));
+        }
+    })
+    .context("RTM_GETLINK dump")?;
+    Ok(kinds)
+}
+
+/// NUL-terminated attribute string.
+pub fn c_str(b: &[u8]) -> String {
+    let end = b.iter().position(|c| *c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..end]).into_owned()
+}
+
+/// NLMSG_ALIGN / NLA_ALIGN
+#[inline]
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+#[inline]
+pub fn u16_at(buf: &[u8], off: usize) -> u16 {
+    buf.get(off..off + 2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+        .unwrap_or(0)
+}
+
+#[inline]
+pub fn u32_at(buf: &[u8], off: usize) -> u32 {
+    buf.get(off..off + 4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+#[inline]
+pub fn u64_at(buf: &[u8], off: usize) -> Option<u64> {
+    buf.get(off..off + 8)
+        .and_then(|b| b.try_into().ok())
+        .map(u64::from_ne_bytes)
+}
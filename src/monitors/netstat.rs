@@ -0,0 +1,49 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::monitor::Monitor;
+use crate::monitors::snmp::{parse_snmp_pairs, KernelCounters};
+
+// Exposes the extended protocol counters of /proc/net/netstat (TcpExt, IpExt, MPTcpExt, ...) as:
+//   netstat{proto="tcpext|ipext|...", key="ListenOverflows|..."} <counter>
+// The file uses the same header/value line pairs as /proc/net/snmp.
+
+pub struct NetstatMonitor {
+    path: PathBuf,
+    // empty exports every key
+    keys: Vec<String>,
+    counters: KernelCounters,
+}
+
+impl NetstatMonitor {
+    pub fn new(registry: &prometheus::Registry, keys: Vec<String>) -> Result<Self> {
+        let counters = KernelCounters::new(registry, "netstat", "Extended protocol counters from /proc/net/netstat")?;
+
+        Ok(Self {
+            path: PathBuf::from("/proc/net/netstat"),
+            keys,
+            counters,
+        })
+    }
+}
+
+impl Monitor for NetstatMonitor {
+    fn name(&self) -> &'static &str {
+        &"netstat"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let content = fs::read_to_string(&self.path).with_context(|| format!("reading {:?}", self.path))?;
+        let pairs = parse_snmp_pairs(&content).with_context(|| format!("parsing {:?}", self.path))?;
+
+        for (section, key, val) in pairs {
+            if !self.keys.is_empty() && !self.keys.iter().any(|k| k == key) {
+                continue;
+            }
+            self.counters.advance(&section.to_ascii_lowercase(), key, val as u64);
+        }
+
+        Ok(())
+    }
+}
@@ -1,31 +1,309 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, ErrorKind},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Context, Result};
-use log::{debug, error, warn};
-use prometheus::{GaugeVec, Opts, Registry};
+use log::{debug, error, info, warn};
+use prometheus::{GaugeVec, IntCounterVec, Opts, Registry};
 
 use crate::monitor::Monitor;
+use crate::monitors::cgroup::CgroupInfo;
+use crate::monitors::proc_events::ProcEvents;
+
+/// Extra per-process data source driven by `ProcessSchedMonitor` for every PID its
+/// selector matches, so additional `/proc/<pid>/*` files share one scan of `/proc`.
+pub trait ProcCollector {
+    fn name(&self) -> &'static str;
+    /// Called once per tick before the first `collect_pid`, to drop per-tick caches.
+    fn begin_tick(&mut self) {}
+    /// `files` holds the /proc/<pid> reads shared with the other collectors of this PID.
+    fn collect_pid(&mut self, target: &ProcTarget, files: &mut PidFiles) -> Result<()>;
+    /// Drops the series of a PID that is no longer matched.
+    fn forget_pid(&mut self, target: &ProcTarget);
+}
+
+/// Labels identifying a process on every per-process metric: `proc`, `pid` and,
+/// with cgroup attribution enabled, `cgroup`, `container_id` and `unit`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcLabels {
+    pub cgroup: bool,
+}
+
+impl ProcLabels {
+    /// Process label names followed by the metric's own `extra` label names.
+    pub fn names<'a>(&self, extra: &[&'a str]) -> Vec<&'a str> {
+        let mut names = vec!["proc", "pid"];
+        if self.cgroup {
+            names.extend_from_slice(&["cgroup", "container_id", "unit"]);
+        }
+        names.extend_from_slice(extra);
+        names
+    }
+}
+
+/// A matched process together with its label values (see `ProcLabels`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcTarget {
+    pub pid: u32,
+    pub comm: String,
+    // the --proc-name selector this process matched
+    pub selector: String,
+    // matched process whose tree this PID belongs to; the PID itself unless it is a descendant
+    pub root: u32,
+    labels: Vec<String>,
+}
+
+impl ProcTarget {
+    fn new(pid: u32, comm: String, selector: String, root: u32, cgroup: Option<CgroupInfo>) -> Self {
+        let mut labels = vec![comm.clone(), pid.to_string()];
+        if let Some(cg) = cgroup {
+            labels.extend([cg.path, cg.container_id, cg.unit]);
+        }
+        Self {
+            pid,
+            comm,
+            selector,
+            root,
+            labels,
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.root == self.pid
+    }
+
+    /// Process label values followed by the metric's own `extra` label values.
+    pub fn labels<'a>(&'a self, extra: &[&'a str]) -> Vec<&'a str> {
+        let mut values: Vec<&str> = self.labels.iter().map(String::as_str).collect();
+        values.extend_from_slice(extra);
+        values
+    }
+}
+
+/// Tracks PIDs whose ptrace-protected files (io, smaps_rollup, fd/, ...) we cannot read,
+/// so a permission failure is logged once per PID instead of on every tick.
+#[derive(Default)]
+pub struct DeniedPids(HashSet<u32>);
+
+impl DeniedPids {
+    /// Reads `path`, returning `None` if access is denied.
+    pub fn read(&mut self, collector: &str, pid: u32, path: &str) -> Result<Option<String>> {
+        self.check(collector, pid, path, fs::read_to_string(path))
+    }
+
+    /// Link targets of the open fds in /proc/<pid>/fd, returning `None` if access is denied.
+    pub fn fd_targets<'a>(&mut self, collector: &str, files: &'a mut PidFiles) -> Result<Option<&'a [String]>> {
+        let pid = files.pid;
+        let dir = format!("/proc/{pid}/fd");
+        self.check(collector, pid, &dir, files.fd_targets())
+    }
+
+    pub fn forget(&mut self, pid: u32) {
+        self.0.remove(&pid);
+    }
+
+    /// Maps a permission failure of an I/O result for `path` to `None`.
+    pub fn check<T>(&mut self, collector: &str, pid: u32, path: &str, res: io::Result<T>) -> Result<Option<T>> {
+        match res {
+            Ok(v) => {
+                self.0.remove(&pid);
+                Ok(Some(v))
+            }
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                if self.0.insert(pid) {
+                    warn!("{collector}: no ptrace access to {path}, skipping until readable");
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e).with_context(|| format!("reading {path}")),
+        }
+    }
+}
+
+/// Reads of /proc/<pid> shared by all collectors of one PID within a tick, each done at most once.
+pub struct PidFiles {
+    pid: u32,
+    fd_targets: Option<io::Result<Vec<String>>>,
+}
+
+impl PidFiles {
+    fn new(pid: u32) -> Self {
+        Self { pid, fd_targets: None }
+    }
+
+    /// Link targets of the open fds in /proc/<pid>/fd.
+    pub fn fd_targets(&mut self) -> io::Result<&[String]> {
+        let pid = self.pid;
+        let res = self.fd_targets.get_or_insert_with(|| {
+            let entries = fs::read_dir(format!("/proc/{pid}/fd"))?;
+            // fds can be closed between read_dir and readlink
+            Ok(entries
+                .flatten()
+                .filter_map(|e| fs::read_link(e.path()).ok())
+                .map(|t| t.to_string_lossy().into_owned())
+                .collect())
+        });
+        match res {
+            Ok(targets) => Ok(targets),
+            // io::Error is not Clone; keep the errno for is_pid_gone and permission checks
+            Err(e) => Err(match e.raw_os_error() {
+                Some(errno) => io::Error::from_raw_os_error(errno),
+                None => io::Error::new(e.kind(), e.to_string()),
+            }),
+        }
+    }
+}
 
-#[derive(Clone)]
 pub struct ProcessSchedMonitor {
-    proc_name_filter: String,
+    // comm prefixes; a PID belongs to the first selector it matches
+    selectors: Vec<String>,
+    collectors: Vec<Box<dyn ProcCollector>>,
+    labels: ProcLabels,
+    // PIDs matched per selector on the previous tick, None before the first scan
+    seen: Vec<Option<HashMap<u32, ProcTarget>>>,
+    // all targets matched on the last tick, shared with samplers running off-tick
+    matched: Arc<Mutex<Vec<ProcTarget>>>,
+    // proc connector listener; when set, /proc is only fully scanned every `rescan_ticks`
+    events: Option<ProcEvents>,
+    rescan_ticks: u64,
+    ticks: u64,
+    // /proc scan cache, fully re-read every `revalidate_ticks` scans
+    pid_cache: HashMap<u32, ScanEntry>,
+    revalidate_ticks: u64,
+    scans: u64,
+    // also track every descendant of a matched process, see `include_descendants`
+    descendants: bool,
+    boot_time: f64,
+    clk_tck: f64,
     nr_migrations: GaugeVec,
     nr_switches: GaugeVec,
     nr_involuntary_switches: GaugeVec,
     nr_voluntary_switches: GaugeVec,
     sum_exec_runtime: GaugeVec,
+    start_time: GaugeVec,
+    matched_pids: GaugeVec,
+    restarts: IntCounterVec,
+    last_exit_time: GaugeVec,
+    errors: IntCounterVec,
+    tree_sched: GaugeVec,
+    tree_stat: GaugeVec,
+}
+
+/// What a /proc scan learned about a PID; starttime tells a reused PID apart.
+struct ScanEntry {
+    // inode and ctime of the /proc/<pid> directory, which a reused PID gets anew
+    dir_id: (u64, i64, i64),
+    starttime: u64,
+    comm: String,
+    matched: bool,
+}
+
+/// A PID to collect this tick: its selector and the root of its process tree.
+struct Member {
+    pid: u32,
+    comm: String,
+    sel_idx: usize,
+    root: u32,
+}
+
+/// Keys of `ProcessSched::values`, shared by every monitor exporting /proc/<pid>/sched.
+pub(crate) const SCHED_KEYS: &[&str] = &[
+    "nr_switches",
+    "nr_voluntary_switches",
+    "nr_involuntary_switches",
+    "nr_migrations",
+    "sum_exec_runtime",
+];
+const TREE_STAT_KEYS: &[&str] = &["utime_seconds", "stime_seconds", "pids"];
+
+/// Sums over the PIDs of one process tree.
+#[derive(Default)]
+struct TreeSums {
+    sched: [f64; SCHED_KEYS.len()],
+    utime_seconds: f64,
+    stime_seconds: f64,
+    pids: f64,
+}
+
+impl TreeSums {
+    /// Values in `TREE_STAT_KEYS` order.
+    fn stat(&self) -> [f64; 3] {
+        [self.utime_seconds, self.stime_seconds, self.pids]
+    }
 }
 
 impl ProcessSchedMonitor {
-    pub fn new(registry: &Registry, proc_name: String) -> Result<Self> {
+    pub fn new(registry: &Registry, selectors: Vec<String>, labels: ProcLabels) -> Result<Self> {
         let make_gauge = |name: &str, help: &str| -> Result<GaugeVec> {
-            let g = GaugeVec::new(Opts::new(name, help), &["proc", "pid"])?;
+            let g = GaugeVec::new(Opts::new(name, help), &labels.names(&[]))?;
+            registry.register(Box::new(g.clone()))?;
+            Ok(g)
+        };
+
+        let make_selector_gauge = |name: &str, help: &str| -> Result<GaugeVec> {
+            let g = GaugeVec::new(Opts::new(name, help), &["selector"])?;
             registry.register(Box::new(g.clone()))?;
             Ok(g)
         };
 
+        let restarts = IntCounterVec::new(
+            Opts::new(
+                "proc_restarts_total",
+                "Number of times a new PID started matching the selector after the first scan",
+            ),
+            &["selector"],
+        )?;
+        registry.register(Box::new(restarts.clone()))?;
+
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "proc_read_errors_total",
+                "Failed reads or parses of /proc/<pid>/* files of a live matched process",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(errors.clone()))?;
+
+        let tree_sched = GaugeVec::new(
+            Opts::new(
+                "proc_tree_sched",
+                "Values from /proc/<pid>/sched summed over a matched process and its descendants",
+            ),
+            &labels.names(&["key"]),
+        )?;
+        registry.register(Box::new(tree_sched.clone()))?;
+
+        let tree_stat = GaugeVec::new(
+            Opts::new(
+                "proc_tree_stat",
+                "CPU time from /proc/<pid>/stat and PID count summed over a matched process and its descendants",
+            ),
+            &labels.names(&["key"]),
+        )?;
+        registry.register(Box::new(tree_stat.clone()))?;
+
         Ok(Self {
-            proc_name_filter: proc_name,
+            seen: vec![None; selectors.len()],
+            selectors,
+            collectors: Vec::new(),
+            labels,
+            matched: Arc::new(Mutex::new(Vec::new())),
+            events: None,
+            rescan_ticks: 1,
+            ticks: 0,
+            pid_cache: HashMap::new(),
+            revalidate_ticks: 12,
+            scans: 0,
+            descendants: false,
+            boot_time: Self::read_boot_time()?,
+            // SAFETY: sysconf has no preconditions
+            clk_tck: unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64,
             nr_migrations: make_gauge("proc_sched_nr_migrations", "se.nr_migrations from /proc/<pid>/sched")?,
             nr_switches: make_gauge("proc_sched_nr_switches", "nr_switches from /proc/<pid>/sched")?,
             nr_involuntary_switches: make_gauge(
@@ -37,16 +315,62 @@ impl ProcessSchedMonitor {
                 "nr_voluntary_switches from /proc/<pid>/sched",
             )?,
             sum_exec_runtime: make_gauge("proc_sum_exec_runtime", "se.sum_exec_runtime from /proc/<pid>/sched")?,
+            start_time: make_gauge(
+                "proc_start_time_seconds",
+                "Process start time since the epoch, from /proc/<pid>/stat starttime plus boot time",
+            )?,
+            matched_pids: make_selector_gauge("proc_matched_pids", "Number of PIDs currently matched by the selector")?,
+            restarts,
+            errors,
+            last_exit_time: make_selector_gauge(
+                "proc_last_exit_time_seconds",
+                "Time since the epoch at which a matched PID was last seen gone",
+            )?,
+            tree_sched,
+            tree_stat,
         })
     }
 
-    fn read_comm(pid: &u32) -> Result<String> {
-        let path = format!("/proc/{pid}/comm");
-        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
-        Ok(content.trim().to_string())
+    pub fn add_collector(&mut self, collector: Box<dyn ProcCollector>) {
+        self.collectors.push(collector);
+    }
+
+    /// Processes matched on the last tick, for collectors that sample on their own schedule.
+    pub fn matched_handle(&self) -> Arc<Mutex<Vec<ProcTarget>>> {
+        self.matched.clone()
+    }
+
+    /// Switches PID discovery to the netlink proc connector, reconciling with a full
+    /// /proc scan at startup and every `rescan_ticks` ticks.
+    pub fn enable_events(&mut self, rescan_ticks: u64) -> Result<()> {
+        self.events = Some(ProcEvents::spawn(self.selectors.clone())?);
+        self.rescan_ticks = rescan_ticks.max(1);
+        Ok(())
+    }
+
+    /// Re-reads comm and starttime of every PID every `ticks` /proc scans instead of
+    /// trusting the scan cache, e.g. to notice a cached unmatched process renaming itself.
+    /// With cgroup labels, the cgroup of matched PIDs is re-read every `ticks` ticks as well.
+    pub fn set_revalidate_ticks(&mut self, ticks: u64) {
+        self.revalidate_ticks = ticks.max(1);
+    }
+
+    /// Also collects every descendant of a matched process (by PPid) under its own comm, and
+    /// exports per-root sums as proc_tree_sched / proc_tree_stat. Needs a full /proc scan each tick.
+    pub fn include_descendants(&mut self) {
+        self.descendants = true;
+    }
+
+    fn read_boot_time() -> Result<f64> {
+        let content = fs::read_to_string("/proc/stat").context("reading /proc/stat")?;
+        content
+            .lines()
+            .find_map(|l| l.strip_prefix("btime "))
+            .and_then(|v| v.trim().parse().ok())
+            .context("missing btime in /proc/stat")
     }
 
-    fn read_sched(pid: u32) -> Result<ProcessSched> {
+    pub(crate) fn read_sched(pid: u32) -> Result<ProcessSched> {
         let path = format!("/proc/{pid}/sched");
         let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
         Self::parse_sched(&content).with_context(|| format!("parsing {path}"))
@@ -93,15 +417,70 @@ impl ProcessSchedMonitor {
             sum_exec_runtime: sum_exec_runtime.context("missing se.sum_exec_runtime")?,
         })
     }
-}
 
-impl Monitor for ProcessSchedMonitor {
-    fn name(&self) -> &'static &str {
-        &"sched"
+    /// Reuses last tick's target for a known PID so /proc/<pid>/cgroup is read once per process.
+    /// Target of a PID, reusing the previous tick's labels unless comm or root changed, or
+    /// `refresh_cgroup` asks to pick up a move to another cgroup.
+    fn target(&self, sel_idx: usize, pid: u32, comm: String, root: u32, refresh_cgroup: bool) -> ProcTarget {
+        if let Some(t) = self.seen[sel_idx].as_ref().and_then(|s| s.get(&pid)) {
+            if t.comm == comm && t.root == root && !(refresh_cgroup && self.labels.cgroup) {
+                return t.clone();
+            }
+        }
+
+        let cgroup = self.labels.cgroup.then(|| {
+            CgroupInfo::read(pid).unwrap_or_else(|e| {
+                debug!("sched: no cgroup attribution for pid {pid}: {e:#}");
+                CgroupInfo::default()
+            })
+        });
+        ProcTarget::new(pid, comm, self.selectors[sel_idx].clone(), root, cgroup)
     }
 
-    fn collect(&mut self) -> Result<()> {
-        let mut matched = 0usize;
+    /// Name and PPid from /proc/<pid>/status.
+    fn read_parent(pid: u32) -> Result<(String, u32)> {
+        let path = format!("/proc/{pid}/status");
+        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+        let field = |name: &str| content.lines().find_map(|l| l.strip_prefix(name)).map(str::trim);
+        let comm = field("Name:").with_context(|| format!("missing Name in {path}"))?;
+        let ppid = field("PPid:")
+            .and_then(|v| v.parse().ok())
+            .with_context(|| format!("missing PPid in {path}"))?;
+        Ok((comm.to_string(), ppid))
+    }
+
+    /// Expands the matched processes to their process trees. A matched process that has a
+    /// matched ancestor is folded into the topmost one, so every PID is counted in one tree.
+    fn with_descendants(&self, matched: Vec<(u32, String)>) -> Result<Vec<Member>> {
+        // pid -> (comm, ppid) of every process on the host
+        let mut procs: HashMap<u32, (String, u32)> = HashMap::new();
+        for entry in fs::read_dir("/proc").context("reading /proc")?.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            // exited or unreadable; it cannot be walked either way
+            if let Ok(p) = Self::read_parent(pid) {
+                procs.insert(pid, p);
+            }
+        }
+
+        let matched: HashMap<u32, (String, usize)> = matched
+            .into_iter()
+            .filter_map(|(pid, comm)| {
+                let idx = match_selector(&self.selectors, &comm)?;
+                Some((pid, (comm, idx)))
+            })
+            .collect();
+
+        Ok(process_trees(&procs, &matched))
+    }
+
+    /// Scan of /proc, returning (pid, comm) of every process matching a selector. Known PIDs are
+    /// answered from the cache: every one is checked for PID reuse by its /proc/<pid> directory
+    /// identity, matched ones by starttime as well, and comm is read for new or reused PIDs.
+    /// Every `revalidate_ticks` scans all entries are re-read.
+    fn scan_proc(&mut self) -> Result<Vec<(u32, String)>> {
+        let mut out = Vec::new();
 
         let entries = fs::read_dir(PathBuf::from("/proc"))
             .with_context(|| "reading /proc")
@@ -110,43 +489,268 @@ impl Monitor for ProcessSchedMonitor {
                 e
             })?;
 
-        for entry_res in entries {
-            let entry = entry_res.map_err(|e| {
-                error!("sched: iterating /proc: {e:#}");
-                e
-            })?;
+        let revalidate = self.scans % self.revalidate_ticks == 0;
+        self.scans += 1;
+        let mut cache = HashMap::with_capacity(self.pid_cache.len());
+        let mut read = 0;
 
-            let ft = entry.file_type().map_err(|e| {
-                error!("sched: reading file_type for {:?}: {e:#}", entry.path());
-                e
-            })?;
-            if !ft.is_dir() {
-                continue;
-            }
+        for entry_res in entries {
+            let entry = match entry_res {
+                Ok(e) => e,
+                Err(e) => {
+                    debug!("sched: iterating /proc: {e:#}");
+                    continue;
+                }
+            };
 
-            // Only numeric PIDs
+            // Only numeric PIDs, which are always directories
             let pid: u32 = match entry.file_name().to_string_lossy().parse::<u32>() {
                 Ok(p) => p,
                 Err(_) => continue,
             };
 
-            let comm = Self::read_comm(&pid).map_err(|e| {
-                error!("sched: reading /proc/{pid}/comm: {e:#}");
-                e
-            })?;
+            // a stat of the directory, cheaper than reading /proc/<pid>/stat of every PID
+            let dir_id = match entry.metadata() {
+                Ok(m) => (m.ino(), m.ctime(), m.ctime_nsec()),
+                Err(e) => {
+                    debug!("sched: stat of /proc/{pid}: {e}");
+                    continue;
+                }
+            };
 
-            if !comm.starts_with(&self.proc_name_filter) {
-                continue;
+            let cached = match self.pid_cache.remove(&pid) {
+                Some(_) if revalidate => None,
+                // the inode can also be recreated for the same process, which only costs a re-read
+                Some(c) if c.dir_id != dir_id => None,
+                // only matched PIDs are worth a stat read per tick
+                Some(c) if c.matched => match read_stat(pid) {
+                    Ok(st) if st.starttime == c.starttime => Some(c),
+                    Ok(_) => None,
+                    Err(e) if is_pid_gone(&e) => continue,
+                    Err(e) => {
+                        error!("sched: reading /proc/{pid}/stat: {e:#}");
+                        continue;
+                    }
+                },
+                Some(c) => Some(c),
+                None => None,
+            };
+
+            // PIDs can exit between read_dir and any of the reads below
+            let entry = match cached {
+                Some(c) => c,
+                None => {
+                    read += 1;
+                    match Self::read_scan_entry(pid, dir_id, &self.selectors) {
+                        Ok(c) => c,
+                        Err(e) if is_pid_gone(&e) => continue,
+                        Err(e) => {
+                            error!("sched: reading /proc/{pid}: {e:#}");
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            if entry.matched {
+                out.push((pid, entry.comm.clone()));
             }
+            cache.insert(pid, entry);
+        }
 
-            let s = Self::read_sched(pid).map_err(|e| {
-                error!("sched: reading/parsing /proc/{pid}/sched (comm={comm}): {e:#}");
-                e
-            })?;
+        debug!(
+            "sched: scanned {} PIDs, read {read}{}",
+            cache.len(),
+            if revalidate { " (revalidation)" } else { "" }
+        );
+        self.pid_cache = cache;
+        Ok(out)
+    }
+
+    fn read_scan_entry(pid: u32, dir_id: (u64, i64, i64), selectors: &[String]) -> Result<ScanEntry> {
+        let comm = read_comm(pid)?;
+        let starttime = read_stat(pid)?.starttime;
+        Ok(ScanEntry {
+            dir_id,
+            starttime,
+            matched: match_selector(selectors, &comm).is_some(),
+            comm,
+        })
+    }
+
+    /// Compares this tick's matches against the previous one: counts restarts, records
+    /// exits and drops the series of PIDs that went away.
+    fn track_lifecycle(&mut self, current: Vec<HashMap<u32, ProcTarget>>, short_lived: &[(u32, String)]) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        for (idx, cur) in current.into_iter().enumerate() {
+            let selector = self.selectors[idx].as_str();
+            self.matched_pids.with_label_values(&[selector]).set(cur.len() as f64);
+
+            if let Some(prev) = &self.seen[idx] {
+                if cur.iter().any(|(pid, t)| t.is_root() && !prev.contains_key(pid)) {
+                    info!("sched: selector '{selector}' matched new PIDs, counting a restart");
+                    self.restarts.with_label_values(&[selector]).inc();
+                }
+
+                // started and exited between two ticks, only known from proc connector events
+                for (pid, comm) in short_lived {
+                    if match_selector(&self.selectors, comm) == Some(idx) {
+                        info!("sched: short-lived pid {pid} (comm={comm}) for selector '{selector}' exited");
+                        self.restarts.with_label_values(&[selector]).inc();
+                        self.last_exit_time.with_label_values(&[selector]).set(now);
+                    }
+                }
+
+                for (pid, target) in prev {
+                    match cur.get(pid) {
+                        // still running with the same labels
+                        Some(t) if t == target => continue,
+                        // renamed or moved to another cgroup; only its old series go away
+                        Some(_) => {}
+                        // a worker or child of a matched process; recycling them is no exit of the service
+                        None if !target.is_root() => {
+                            debug!(
+                                "sched: descendant pid {pid} (comm={}) of {} exited",
+                                target.comm, target.root
+                            );
+                        }
+                        None => {
+                            info!(
+                                "sched: pid {pid} (comm={}) for selector '{selector}' exited",
+                                target.comm
+                            );
+                            self.last_exit_time.with_label_values(&[selector]).set(now);
+                        }
+                    }
+
+                    let labels = &target.labels(&[]);
+                    for g in [
+                        &self.nr_migrations,
+                        &self.nr_switches,
+                        &self.nr_involuntary_switches,
+                        &self.nr_voluntary_switches,
+                        &self.sum_exec_runtime,
+                        &self.start_time,
+                    ] {
+                        let _ = g.remove_label_values(labels);
+                    }
+                    let _ = self.errors.remove_label_values(labels);
+                    if target.is_root() {
+                        for key in SCHED_KEYS {
+                            let _ = self.tree_sched.remove_label_values(&target.labels(&[key]));
+                        }
+                        for key in TREE_STAT_KEYS {
+                            let _ = self.tree_stat.remove_label_values(&target.labels(&[key]));
+                        }
+                    }
+                    for c in &mut self.collectors {
+                        c.forget_pid(target);
+                    }
+                }
+            }
+
+            self.seen[idx] = Some(cur);
+        }
+    }
+}
+
+impl Monitor for ProcessSchedMonitor {
+    fn name(&self) -> &'static &str {
+        &"sched"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let mut current: Vec<HashMap<u32, ProcTarget>> = vec![HashMap::new(); self.selectors.len()];
+
+        let mut short_lived = Vec::new();
+        let mut targets = None;
+        if let Some(events) = &self.events {
+            if self.ticks % self.rescan_ticks != 0 {
+                let snap = events.snapshot();
+                if !snap.lost_events {
+                    short_lived = snap.short_lived;
+                    targets = Some(snap.matched);
+                }
+            }
+        }
+        let targets = match targets {
+            Some(t) => t,
+            None => {
+                let since = self.events.as_ref().map(ProcEvents::begin_scan);
+                let targets = self.scan_proc()?;
+                if let (Some(events), Some(since)) = (&self.events, since) {
+                    events.reconcile(since, &targets);
+                }
+                targets
+            }
+        };
+        self.ticks += 1;
+
+        let members = if self.descendants {
+            self.with_descendants(targets)?
+        } else {
+            targets
+                .into_iter()
+                .filter_map(|(pid, comm)| {
+                    let sel_idx = match_selector(&self.selectors, &comm)?;
+                    Some(Member {
+                        pid,
+                        comm,
+                        sel_idx,
+                        root: pid,
+                    })
+                })
+                .collect()
+        };
+
+        let refresh_cgroup = self.ticks % self.revalidate_ticks == 0;
+        for c in &mut self.collectors {
+            c.begin_tick();
+        }
 
-            matched += 1;
-            let pid_s = pid.to_string();
-            let labels = &[comm.as_str(), pid_s.as_str()];
+        let mut trees: HashMap<u32, TreeSums> = HashMap::new();
+
+        for Member {
+            pid,
+            comm,
+            sel_idx,
+            root,
+        } in members
+        {
+            let target = self.target(sel_idx, pid, comm, root, refresh_cgroup);
+            let labels = &target.labels(&[]);
+
+            let s = match Self::read_sched(pid) {
+                Ok(s) => s,
+                Err(e) if is_pid_gone(&e) => {
+                    debug!("sched: pid {pid} exited before /proc/{pid}/sched was read");
+                    continue;
+                }
+                Err(e) => {
+                    error!("sched: reading/parsing /proc/{pid}/sched (comm={}): {e:#}", target.comm);
+                    self.errors.with_label_values(labels).inc();
+                    current[sel_idx].insert(pid, target);
+                    continue;
+                }
+            };
+
+            // read before setting anything, so a PID that exits in between leaves no series behind
+            let stat = match read_stat(pid) {
+                Ok(st) => Some(st),
+                Err(e) if is_pid_gone(&e) => {
+                    debug!("sched: pid {pid} exited before /proc/{pid}/stat was read");
+                    continue;
+                }
+                Err(e) => {
+                    error!("sched: reading/parsing /proc/{pid}/stat (comm={}): {e:#}", target.comm);
+                    self.errors.with_label_values(labels).inc();
+                    None
+                }
+            };
 
             self.nr_migrations.with_label_values(labels).set(s.nr_migrations as f64);
             self.nr_switches.with_label_values(labels).set(s.nr_switches as f64);
@@ -157,23 +761,274 @@ impl Monitor for ProcessSchedMonitor {
                 .with_label_values(labels)
                 .set(s.nr_voluntary_switches as f64);
             self.sum_exec_runtime.with_label_values(labels).set(s.sum_exec_runtime);
+
+            let tree = trees.entry(root).or_default();
+            for (sum, v) in tree.sched.iter_mut().zip(s.values()) {
+                *sum += v;
+            }
+            tree.pids += 1.0;
+
+            if let Some(st) = stat {
+                self.start_time
+                    .with_label_values(labels)
+                    .set(self.boot_time + st.starttime as f64 / self.clk_tck);
+                tree.utime_seconds += st.utime as f64 / self.clk_tck;
+                tree.stime_seconds += st.stime as f64 / self.clk_tck;
+            }
+
+            let mut files = PidFiles::new(pid);
+            for c in &mut self.collectors {
+                match c.collect_pid(&target, &mut files) {
+                    Ok(()) => {}
+                    Err(e) if is_pid_gone(&e) => {}
+                    Err(e) => {
+                        error!("sched: {} failed for pid {pid} (comm={}): {e:#}", c.name(), target.comm);
+                        self.errors.with_label_values(labels).inc();
+                    }
+                }
+            }
+
+            current[sel_idx].insert(pid, target);
         }
 
-        if matched == 0 {
-            warn!("sched: no processes with prefix '{}' found", self.proc_name_filter);
-        } else {
-            debug!("comm prefix '{}' matched {} PIDs", self.proc_name_filter, matched);
+        for (selector, cur) in self.selectors.iter().zip(&current) {
+            if cur.is_empty() {
+                warn!("sched: no processes with prefix '{selector}' found");
+            } else {
+                debug!("comm prefix '{selector}' matched {} PIDs", cur.len());
+            }
+        }
+        if self.descendants {
+            for root in current.iter().flat_map(|c| c.values()).filter(|t| t.is_root()) {
+                let Some(sums) = trees.get(&root.pid) else {
+                    continue;
+                };
+                for (key, v) in SCHED_KEYS.iter().zip(sums.sched) {
+                    self.tree_sched.with_label_values(&root.labels(&[key])).set(v);
+                }
+                for (key, v) in TREE_STAT_KEYS.iter().zip(sums.stat()) {
+                    self.tree_stat.with_label_values(&root.labels(&[key])).set(v);
+                }
+            }
         }
+        *self.matched.lock().unwrap() = current.iter().flat_map(|c| c.values().cloned()).collect();
+        self.track_lifecycle(current, &short_lived);
 
         Ok(())
     }
 }
 
 #[derive(Debug)]
-struct ProcessSched {
-    nr_migrations: u64,
-    nr_switches: u64,
-    nr_involuntary_switches: u64,
-    nr_voluntary_switches: u64,
-    sum_exec_runtime: f64,
+pub(crate) struct ProcessSched {
+    pub nr_migrations: u64,
+    pub nr_switches: u64,
+    pub nr_involuntary_switches: u64,
+    pub nr_voluntary_switches: u64,
+    pub sum_exec_runtime: f64,
+}
+
+/// True if `e` comes from reading /proc/<pid>/* of a process that has already exited
+/// (ENOENT for the files, ESRCH for some reads of a zombie's task state).
+pub fn is_pid_gone(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|c| c.downcast_ref::<io::Error>())
+        .any(|io| matches!(io.raw_os_error(), Some(libc::ENOENT) | Some(libc::ESRCH)))
+}
+
+/// Members of the process trees rooted at the `matched` processes (pid -> (comm, selector)),
+/// given (comm, ppid) of every process. A matched process below another matched one belongs
+/// to the topmost one's tree, and ppid cycles are cut.
+fn process_trees(procs: &HashMap<u32, (String, u32)>, matched: &HashMap<u32, (String, usize)>) -> Vec<Member> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (pid, (_, ppid)) in procs {
+        children.entry(*ppid).or_default().push(*pid);
+    }
+
+    let has_matched_ancestor = |pid: u32| {
+        // on a ppid cycle the walk comes back to pid itself, which is no ancestor
+        let mut visited = HashSet::from([pid]);
+        let mut cur = procs.get(&pid).map(|(_, ppid)| *ppid);
+        while let Some(p) = cur.filter(|p| *p > 1 && visited.insert(*p)) {
+            if matched.contains_key(&p) {
+                return true;
+            }
+            cur = procs.get(&p).map(|(_, ppid)| *ppid);
+        }
+        false
+    };
+
+    let mut out = Vec::new();
+    let mut visited = HashSet::new();
+    for (&root, (comm, sel_idx)) in matched {
+        if has_matched_ancestor(root) {
+            continue;
+        }
+        out.push(Member {
+            pid: root,
+            comm: comm.clone(),
+            sel_idx: *sel_idx,
+            root,
+        });
+        visited.insert(root);
+
+        let mut stack: Vec<u32> = children.get(&root).cloned().unwrap_or_default();
+        while let Some(pid) = stack.pop() {
+            if !visited.insert(pid) {
+                continue;
+            }
+            out.push(Member {
+                pid,
+                comm: procs[&pid].0.clone(),
+                sel_idx: *sel_idx,
+                root,
+            });
+            stack.extend(children.get(&pid).into_iter().flatten());
+        }
+    }
+
+    out
+}
+
+/// Index of the first selector (comm prefix) matching `comm`.
+pub fn match_selector(selectors: &[String], comm: &str) -> Option<usize> {
+    selectors.iter().position(|s| comm.starts_with(s.as_str()))
+}
+
+/// Fields of /proc/<pid>/stat used by the process monitors.
+#[derive(Debug)]
+pub struct ProcessStat {
+    pub state: char,
+    pub ppid: u32,
+    // clock ticks spent in user and kernel mode, summed over threads
+    pub utime: u64,
+    pub stime: u64,
+    // clock ticks since boot
+    pub starttime: u64,
+    pub rt_priority: u32,
+    // SCHED_* constant
+    pub policy: u32,
+}
+
+impl ProcessSched {
+    /// Values in `SCHED_KEYS` order.
+    pub fn values(&self) -> [f64; 5] {
+        [
+            self.nr_switches as f64,
+            self.nr_voluntary_switches as f64,
+            self.nr_involuntary_switches as f64,
+            self.nr_migrations as f64,
+            self.sum_exec_runtime,
+        ]
+    }
+}
+
+/// /proc/<pid>/comm without the trailing newline.
+pub fn read_comm(pid: u32) -> Result<String> {
+    let path = format!("/proc/{pid}/comm");
+    let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+    Ok(content.trim().to_string())
+}
+
+pub fn read_stat(pid: u32) -> Result<ProcessStat> {
+    read_stat_file(format!("/proc/{pid}/stat"))
+}
+
+/// /proc/<pid>/task/<tid>/stat, with the per-thread state and times.
+pub fn read_task_stat(pid: u32, tid: u32) -> Result<ProcessStat> {
+    read_stat_file(format!("/proc/{pid}/task/{tid}/stat"))
+}
+
+fn read_stat_file(path: String) -> Result<ProcessStat> {
+    let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+    parse_stat(&content).with_context(|| format!("parsing {path}"))
+}
+
+fn parse_stat(content: &str) -> Result<ProcessStat> {
+    // comm may contain spaces and parens, so split after the last ')'
+    let (_, rest) = content.rsplit_once(')').context("missing ')' after comm")?;
+    // rest starts at field 3 (state); field N is at index N - 3
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3).with_context(|| format!("missing field {n}"));
+
+    Ok(ProcessStat {
+        state: field(3)?.chars().next().context("empty state")?,
+        ppid: field(4)?.parse().context("parsing ppid")?,
+        utime: field(14)?.parse().context("parsing utime")?,
+        stime: field(15)?.parse().context("parsing stime")?,
+        starttime: field(22)?.parse().context("parsing starttime")?,
+        rt_priority: field(40)?.parse().context("parsing rt_priority")?,
+        policy: field(41)?.parse().context("parsing policy")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat_with_parens_in_comm() {
+        let content = "4242 (my (odd) prog) S 1 4242 4242 0 -1 4194560 1800 0 0 0 \
+                       150 75 0 0 20 0 3 0 123456 0 0 18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 \
+                       17 2 50 1 0 0 0\n";
+        let st = parse_stat(content).unwrap();
+        assert_eq!(st.state, 'S');
+        assert_eq!(st.ppid, 1);
+        assert_eq!((st.utime, st.stime), (150, 75));
+        assert_eq!(st.starttime, 123456);
+        assert_eq!((st.rt_priority, st.policy), (50, 1));
+    }
+
+    #[test]
+    fn parse_stat_rejects_truncated_lines() {
+        assert!(parse_stat("4242 (prog) S 1 4242").is_err());
+        assert!(parse_stat("4242 prog S").is_err());
+    }
+
+    fn trees(procs: &[(u32, &str, u32)], matched: &[(u32, usize)]) -> Vec<(u32, u32, usize)> {
+        let procs: HashMap<u32, (String, u32)> = procs
+            .iter()
+            .map(|(pid, comm, ppid)| (*pid, (comm.to_string(), *ppid)))
+            .collect();
+        let matched = matched
+            .iter()
+            .map(|(pid, idx)| (*pid, (procs[pid].0.clone(), *idx)))
+            .collect();
+        let mut out: Vec<_> = process_trees(&procs, &matched)
+            .into_iter()
+            .map(|m| (m.pid, m.root, m.sel_idx))
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn process_trees_fold_matched_descendants_into_the_topmost_root() {
+        let procs = [
+            (1, "init", 0),
+            (10, "nginx", 1),
+            (11, "nginx", 10),
+            (12, "worker", 11),
+            (13, "sh", 12),
+            // forked by 12, reparented to init when 12's parent exited
+            (20, "orphan", 1),
+        ];
+        assert_eq!(
+            trees(&procs, &[(10, 0), (11, 0)]),
+            [(10, 10, 0), (11, 10, 0), (12, 10, 0), (13, 10, 0)]
+        );
+    }
+
+    #[test]
+    fn process_trees_follow_reparented_children() {
+        // 31 was reparented to the matched subreaper 30 after its parent exited
+        let procs = [(1, "init", 0), (30, "supervisor", 1), (31, "job", 30), (40, "job", 1)];
+        assert_eq!(trees(&procs, &[(30, 1)]), [(30, 30, 1), (31, 30, 1)]);
+    }
+
+    #[test]
+    fn process_trees_cut_ppid_cycles() {
+        // a reused PID read between two /proc/<pid>/status reads can close a loop
+        let procs = [(50, "a", 51), (51, "b", 50), (52, "c", 51)];
+        assert_eq!(trees(&procs, &[(50, 0)]), [(50, 50, 0), (51, 50, 0), (52, 50, 0)]);
+    }
 }
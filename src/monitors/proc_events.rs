@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+
+use crate::monitors::netlink::{u32_at, NLMSG_HDRLEN};
+use crate::monitors::proc::{match_selector, read_comm};
+
+// Keeps the set of PIDs matched by the sched selectors up to date from the kernel
+// proc connector (NETLINK_CONNECTOR / CN_IDX_PROC) instead of scanning /proc every tick.
+// Layouts follow include/uapi/linux/connector.h and include/uapi/linux/cn_proc.h.
+
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_COMM: u32 = 0x0000_0200;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+// struct cn_msg: cb_id { idx, val }, seq, ack, len (u16), flags (u16)
+const CN_MSG_LEN: usize = 20;
+// struct proc_event: what, cpu, timestamp_ns, then the event_data union
+const PROC_EVENT_DATA_OFF: usize = 16;
+
+#[derive(Clone)]
+enum Change {
+    Matched(String),
+    Unmatched,
+    Exited,
+}
+
+#[derive(Default)]
+struct EventState {
+    // pid -> (comm, handed out by a snapshot yet)
+    matched: HashMap<u32, (String, bool)>,
+    // matched PIDs that exited before any snapshot saw them
+    short_lived: Vec<(u32, String)>,
+    // set when the socket overran and events were lost
+    lost_events: bool,
+    // the listener thread gave up; every snapshot then asks for a full scan
+    stopped: bool,
+    // bumped for every event and overrun
+    generation: u64,
+    // generation of the last overrun
+    lost_generation: u64,
+    // events received since begin_scan, replayed on top of the scan by reconcile
+    journal: Option<Vec<(u64, u32, Change)>>,
+}
+
+impl EventState {
+    fn record(&mut self, pid: u32, change: Change) {
+        self.generation += 1;
+        let generation = self.generation;
+        if let Some(journal) = &mut self.journal {
+            journal.push((generation, pid, change.clone()));
+        }
+        self.apply(pid, change);
+    }
+
+    fn record_lost(&mut self) {
+        self.generation += 1;
+        self.lost_generation = self.generation;
+        self.lost_events = true;
+    }
+
+    fn apply(&mut self, pid: u32, change: Change) {
+        match change {
+            Change::Matched(comm) => {
+                let reported = self.matched.get(&pid).is_some_and(|(_, r)| *r);
+                self.matched.insert(pid, (comm, reported));
+            }
+            Change::Unmatched => {
+                self.matched.remove(&pid);
+            }
+            Change::Exited => {
+                if let Some((comm, reported)) = self.matched.remove(&pid) {
+                    debug!("proc_events: matched pid {pid} (comm={comm}) exited");
+                    if !reported {
+                        self.short_lived.push((pid, comm));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct ProcEvents {
+    state: Arc<Mutex<EventState>>,
+}
+
+/// Matched PIDs as seen by the event listener since the previous snapshot.
+pub struct EventSnapshot {
+    pub matched: Vec<(u32, String)>,
+    pub short_lived: Vec<(u32, String)>,
+    pub lost_events: bool,
+}
+
+impl ProcEvents {
+    /// Subscribes to the proc connector and starts a listener thread.
+    /// Needs CAP_NET_ADMIN.
+    pub fn spawn(selectors: Vec<String>) -> Result<Self> {
+        let sock = Self::subscribe().context("subscribing to the netlink proc connector")?;
+        let state = Arc::new(Mutex::new(EventState::default()));
+
+        let thread_state = state.clone();
+        thread::Builder::new()
+            .name("proc-events".to_string())
+            .spawn(move || Self::listen(sock, &selectors, &thread_state))
+            .context("spawning proc connector thread")?;
+
+        info!("proc_events: listening for PROC_EVENT_EXEC/FORK/COMM/EXIT");
+        Ok(Self { state })
+    }
+
+    /// Returns the currently matched PIDs and drains short-lived ones.
+    pub fn snapshot(&self) -> EventSnapshot {
+        let mut st = self.state.lock().unwrap();
+        // no scan is running, drop the journal of one that failed
+        st.journal = None;
+        let matched = st
+            .matched
+            .iter_mut()
+            .map(|(pid, (comm, reported))| {
+                *reported = true;
+                (*pid, comm.clone())
+            })
+            .collect();
+
+        EventSnapshot {
+            matched,
+            short_lived: mem::take(&mut st.short_lived),
+            lost_events: mem::take(&mut st.lost_events) || st.stopped,
+        }
+    }
+
+    /// Marks the start of a full /proc scan; events from here on are kept for `reconcile`.
+    /// Returns the generation to pass to it.
+    pub fn begin_scan(&self) -> u64 {
+        let mut st = self.state.lock().unwrap();
+        st.journal = Some(Vec::new());
+        st.generation
+    }
+
+    /// Replaces the tracked set with the result of a full /proc scan started at generation
+    /// `since`, then replays the events received while it ran, which are newer than the scan.
+    pub fn reconcile(&self, since: u64, matched: &[(u32, String)]) {
+        let mut st = self.state.lock().unwrap();
+        st.matched = matched.iter().map(|(pid, comm)| (*pid, (comm.clone(), true))).collect();
+        st.short_lived.clear();
+        for (generation, pid, change) in st.journal.take().unwrap_or_default() {
+            if generation > since {
+                st.apply(pid, change);
+            }
+        }
+        // an overrun during the scan may have dropped events the scan did not see either
+        st.lost_events = st.lost_generation > since;
+    }
+
+    fn subscribe() -> Result<OwnedFd> {
+        // SAFETY: plain socket(2) call, the returned fd is owned below
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM, libc::NETLINK_CONNECTOR) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("socket(NETLINK_CONNECTOR)");
+        }
+        // SAFETY: fd is a freshly created, valid socket
+        let sock = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain old data, all-zero is a valid value
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = CN_IDX_PROC;
+        // SAFETY: addr is a valid sockaddr_nl of the given size
+        let rc = unsafe {
+            libc::bind(
+                sock.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error()).context("bind(CN_IDX_PROC)");
+        }
+
+        let total = NLMSG_HDRLEN + CN_MSG_LEN + 4;
+        let mut msg = Vec::with_capacity(total);
+        // nlmsghdr
+        msg.extend_from_slice(&(total as u32).to_ne_bytes());
+        msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&std::process::id().to_ne_bytes());
+        // cn_msg
+        msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&4u16.to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        // enum proc_cn_mcast_op
+        msg.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+        // SAFETY: msg is a valid buffer of msg.len() bytes
+        let rc = unsafe { libc::send(sock.as_raw_fd(), msg.as_ptr() as *const libc::c_void, msg.len(), 0) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error()).context("sending PROC_CN_MCAST_LISTEN");
+        }
+
+        Ok(sock)
+    }
+
+    fn listen(sock: OwnedFd, selectors: &[String], state: &Mutex<EventState>) {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            // SAFETY: buf is a valid writable buffer of buf.len() bytes
+            let n = unsafe { libc::recv(sock.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(libc::ENOBUFS) => {
+                        warn!("proc_events: socket overrun, events lost; forcing a rescan");
+                        state.lock().unwrap().record_lost();
+                        continue;
+                    }
+                    _ => {
+                        error!("proc_events: recv failed, stopping listener: {e}");
+                        state.lock().unwrap().stopped = true;
+                        return;
+                    }
+                }
+            }
+
+            let mut msg = &buf[..n as usize];
+            while msg.len() >= NLMSG_HDRLEN {
+                let len = u32_at(msg, 0) as usize;
+                if len < NLMSG_HDRLEN || len > msg.len() {
+                    break;
+                }
+                if let Some(event) = msg.get(NLMSG_HDRLEN + CN_MSG_LEN..len) {
+                    Self::handle_event(event, selectors, state);
+                }
+                // NLMSG_ALIGN
+                msg = &msg[((len + 3) & !3).min(msg.len())..];
+            }
+        }
+    }
+
+    fn handle_event(ev: &[u8], selectors: &[String], state: &Mutex<EventState>) {
+        if ev.len() < PROC_EVENT_DATA_OFF + 8 {
+            return;
+        }
+        let what = u32_at(ev, 0);
+        let data = &ev[PROC_EVENT_DATA_OFF..];
+
+        match what {
+            PROC_EVENT_FORK if data.len() >= 16 => {
+                // parent_pid, parent_tgid, child_pid, child_tgid; only new processes, not threads
+                let (child_pid, child_tgid) = (u32_at(data, 8), u32_at(data, 12));
+                if child_pid == child_tgid {
+                    // the process may already be gone by the time we look
+                    Self::update(child_tgid, read_comm(child_tgid).ok(), selectors, state);
+                }
+            }
+            PROC_EVENT_EXEC => {
+                let tgid = u32_at(data, 4);
+                Self::update(tgid, read_comm(tgid).ok(), selectors, state);
+            }
+            PROC_EVENT_COMM if data.len() >= 24 => {
+                // process_pid, process_tgid, comm[16]; thread renames don't change the process comm
+                let (pid, tgid) = (u32_at(data, 0), u32_at(data, 4));
+                if pid == tgid {
+                    let raw = &data[8..24];
+                    let end = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+                    let comm = String::from_utf8_lossy(&raw[..end]).to_string();
+                    Self::update(tgid, Some(comm), selectors, state);
+                }
+            }
+            PROC_EVENT_EXIT => {
+                let (pid, tgid) = (u32_at(data, 0), u32_at(data, 4));
+                if pid != tgid {
+                    return;
+                }
+                state.lock().unwrap().record(tgid, Change::Exited);
+            }
+            _ => {}
+        }
+    }
+
+    fn update(pid: u32, comm: Option<String>, selectors: &[String], state: &Mutex<EventState>) {
+        let change = match comm {
+            Some(comm) if match_selector(selectors, &comm).is_some() => {
+                debug!("proc_events: pid {pid} (comm={comm}) matched");
+                Change::Matched(comm)
+            }
+            _ => Change::Unmatched,
+        };
+        state.lock().unwrap().record(pid, change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> ProcEvents {
+        ProcEvents {
+            state: Arc::new(Mutex::new(EventState::default())),
+        }
+    }
+
+    #[test]
+    fn reconcile_replays_events_received_during_the_scan() {
+        let ev = events();
+        ev.state.lock().unwrap().record(10, Change::Matched("old".to_string()));
+
+        let since = ev.begin_scan();
+        {
+            let mut st = ev.state.lock().unwrap();
+            // 20 exits after the scan listed it, 30 starts after the scan passed its pid
+            st.record(20, Change::Exited);
+            st.record(30, Change::Matched("new".to_string()));
+        }
+        ev.reconcile(since, &[(10, "old".to_string()), (20, "gone".to_string())]);
+
+        let snap = ev.snapshot();
+        let mut pids: Vec<u32> = snap.matched.iter().map(|(pid, _)| *pid).collect();
+        pids.sort();
+        assert_eq!(pids, [10, 30]);
+        assert!(!snap.lost_events);
+    }
+
+    #[test]
+    fn overrun_during_the_scan_keeps_lost_events() {
+        let ev = events();
+        let since = ev.begin_scan();
+        ev.state.lock().unwrap().record_lost();
+        ev.reconcile(since, &[]);
+        assert!(ev.snapshot().lost_events);
+    }
+}
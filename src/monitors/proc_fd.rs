@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitors::proc::{DeniedPids, PidFiles, ProcCollector, ProcLabels, ProcTarget};
+
+// Exposes open file descriptors of matched processes as:
+//   proc_fds{proc="<comm>", pid="<pid>", type="socket|pipe|eventfd|epoll|anon_inode|file|other"} <count>
+//   proc_fd_usage_ratio{proc="<comm>", pid="<pid>"} <open fds / soft "Max open files">
+const FD_TYPES: &[&str] = &["socket", "pipe", "eventfd", "epoll", "anon_inode", "file", "other"];
+
+pub struct ProcFdCollector {
+    fds: GaugeVec,
+    usage_ratio: GaugeVec,
+    denied: DeniedPids,
+}
+
+impl ProcFdCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let fds = GaugeVec::new(
+            Opts::new("proc_fds", "Open file descriptors from /proc/<pid>/fd, by type"),
+            &labels.names(&["type"]),
+        )?;
+        registry.register(Box::new(fds.clone()))?;
+
+        let usage_ratio = GaugeVec::new(
+            Opts::new(
+                "proc_fd_usage_ratio",
+                "Open file descriptors divided by the soft 'Max open files' limit",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(usage_ratio.clone()))?;
+
+        Ok(Self {
+            fds,
+            usage_ratio,
+            denied: DeniedPids::default(),
+        })
+    }
+
+    fn fd_type(target: &str) -> &'static str {
+        // readlink targets, e.g. "socket:[1234]", "anon_inode:[eventpoll]", "/var/log/x.log"
+        if target.starts_with("socket:") {
+            "socket"
+        } else if target.starts_with("pipe:") {
+            "pipe"
+        } else if target == "anon_inode:[eventfd]" {
+            "eventfd"
+        } else if target == "anon_inode:[eventpoll]" {
+            "epoll"
+        } else if target.starts_with("anon_inode:") {
+            "anon_inode"
+        } else if target.starts_with('/') {
+            "file"
+        } else {
+            "other"
+        }
+    }
+
+    /// Returns fd counts by type, or `None` if the fd directory is not accessible.
+    fn count_fds(&mut self, files: &mut PidFiles) -> Result<Option<BTreeMap<&'static str, u64>>> {
+        let Some(targets) = self.denied.fd_targets("proc_fd", files)? else {
+            return Ok(None);
+        };
+
+        let mut counts: BTreeMap<&'static str, u64> = BTreeMap::new();
+        for target in targets {
+            *counts.entry(Self::fd_type(target)).or_insert(0) += 1;
+        }
+
+        Ok(Some(counts))
+    }
+}
+
+impl ProcCollector for ProcFdCollector {
+    fn name(&self) -> &'static str {
+        "proc_fd"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let Some(counts) = self.count_fds(files)? else {
+            return Ok(());
+        };
+
+        for ty in FD_TYPES {
+            let n = counts.get(ty).copied().unwrap_or(0);
+            self.fds.with_label_values(&target.labels(&[ty])).set(n as f64);
+        }
+
+        let path = format!("/proc/{pid}/limits");
+        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+        let soft = parse_limits(&content)
+            .into_iter()
+            .find(|l| l.name == "max_open_files")
+            .and_then(|l| l.soft);
+        match soft {
+            Some(soft) if soft > 0 => {
+                let open: u64 = counts.values().sum();
+                self.usage_ratio
+                    .with_label_values(&target.labels(&[]))
+                    .set(open as f64 / soft as f64);
+            }
+            _ => debug!("proc_fd: no finite 'Max open files' limit in {path}"),
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for ty in FD_TYPES {
+            let _ = self.fds.remove_label_values(&target.labels(&[ty]));
+        }
+        let _ = self.usage_ratio.remove_label_values(&target.labels(&[]));
+        self.denied.forget(target.pid);
+    }
+}
+
+// Exposes /proc/<pid>/limits for matched processes as:
+//   proc_limit{proc="<comm>", pid="<pid>", limit="max_open_files", kind="soft|hard"} <value>
+// "unlimited" is exported as +Inf.
+pub struct ProcLimitsCollector {
+    limits: GaugeVec,
+    // every limit label set so far, to drop series of exited PIDs
+    names: BTreeSet<String>,
+}
+
+impl ProcLimitsCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let limits = GaugeVec::new(
+            Opts::new("proc_limit", "Soft and hard resource limits from /proc/<pid>/limits"),
+            &labels.names(&["limit", "kind"]),
+        )?;
+        registry.register(Box::new(limits.clone()))?;
+        Ok(Self {
+            limits,
+            names: BTreeSet::new(),
+        })
+    }
+}
+
+impl ProcCollector for ProcLimitsCollector {
+    fn name(&self) -> &'static str {
+        "proc_limits"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let path = format!("/proc/{pid}/limits");
+        let content = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+
+        for l in parse_limits(&content) {
+            for (kind, v) in [("soft", l.soft), ("hard", l.hard)] {
+                self.limits
+                    .with_label_values(&target.labels(&[l.name.as_str(), kind]))
+                    .set(v.map_or(f64::INFINITY, |v| v as f64));
+            }
+            self.names.insert(l.name);
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for name in &self.names {
+            for kind in ["soft", "hard"] {
+                let _ = self.limits.remove_label_values(&target.labels(&[name.as_str(), kind]));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Limit {
+    // snake_case of the limit name, e.g. "max_open_files"
+    name: String,
+    // None means "unlimited"
+    soft: Option<u64>,
+    hard: Option<u64>,
+}
+
+fn parse_limits(content: &str) -> Vec<Limit> {
+    let parse = |v: &str| -> Option<Option<u64>> {
+        match v {
+            "unlimited" => Some(None),
+            _ => v.parse().ok().map(Some),
+        }
+    };
+
+    let mut out = Vec::new();
+    // skip header line ("Limit  Soft Limit  Hard Limit  Units")
+    for line in content.lines().skip(1) {
+        // e.g. "Max open files            20000                20000                files"
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        let Some(i) = toks.iter().position(|t| parse(t).is_some()) else {
+            continue;
+        };
+        let (Some(soft), Some(hard)) = (parse(toks[i]), toks.get(i + 1).and_then(|t| parse(t))) else {
+            continue;
+        };
+
+        out.push(Limit {
+            name: toks[..i].join("_").to_lowercase(),
+            soft,
+            hard,
+        });
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_limits_table() {
+        let content = "\
+Limit                     Soft Limit           Hard Limit           Units
+Max cpu time              unlimited            unlimited            seconds
+Max open files            1024                 524288               files
+Max realtime timeout      unlimited            unlimited            us
+";
+        let limits = parse_limits(content);
+        let names: Vec<&str> = limits.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["max_cpu_time", "max_open_files", "max_realtime_timeout"]);
+        assert_eq!((limits[0].soft, limits[0].hard), (None, None));
+        assert_eq!((limits[1].soft, limits[1].hard), (Some(1024), Some(524288)));
+    }
+}
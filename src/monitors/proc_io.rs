@@ -0,0 +1,93 @@
+use anyhow::Result;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitors::proc::{DeniedPids, PidFiles, ProcCollector, ProcLabels, ProcTarget};
+
+// Exposes /proc/<pid>/io for matched processes as:
+//   proc_io{proc="<comm>", pid="<pid>", key="<field>"} <value>
+// Reading the file requires ptrace access to the target, so permission failures
+// are surfaced via proc_io_access_denied instead of errors.
+
+const IO_KEYS: &[&str] = &[
+    "rchar",
+    "wchar",
+    "syscr",
+    "syscw",
+    "read_bytes",
+    "write_bytes",
+    "cancelled_write_bytes",
+];
+
+pub struct ProcIoCollector {
+    io: GaugeVec,
+    access_denied: GaugeVec,
+    denied: DeniedPids,
+}
+
+impl ProcIoCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let io = GaugeVec::new(
+            Opts::new("proc_io", "I/O accounting counters from /proc/<pid>/io"),
+            &labels.names(&["key"]),
+        )?;
+        registry.register(Box::new(io.clone()))?;
+
+        let access_denied = GaugeVec::new(
+            Opts::new(
+                "proc_io_access_denied",
+                "1 if /proc/<pid>/io could not be read due to missing ptrace access",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(access_denied.clone()))?;
+
+        Ok(Self {
+            io,
+            access_denied,
+            denied: DeniedPids::default(),
+        })
+    }
+
+    fn parse_io(content: &str) -> Vec<(&str, u64)> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let (k, v) = line.split_once(':')?;
+                let k = k.trim();
+                if !IO_KEYS.contains(&k) {
+                    return None;
+                }
+                Some((k, v.trim().parse().ok()?))
+            })
+            .collect()
+    }
+}
+
+impl ProcCollector for ProcIoCollector {
+    fn name(&self) -> &'static str {
+        "proc_io"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let Some(content) = self.denied.read("proc_io", pid, &format!("/proc/{pid}/io"))? else {
+            self.access_denied.with_label_values(&target.labels(&[])).set(1.0);
+            return Ok(());
+        };
+        self.access_denied.with_label_values(&target.labels(&[])).set(0.0);
+
+        for (key, val) in Self::parse_io(&content) {
+            self.io.with_label_values(&target.labels(&[key])).set(val as f64);
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for key in IO_KEYS {
+            let _ = self.io.remove_label_values(&target.labels(&[key]));
+        }
+        let _ = self.access_denied.remove_label_values(&target.labels(&[]));
+        self.denied.forget(target.pid);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitors::proc::{DeniedPids, PidFiles, ProcCollector, ProcLabels, ProcTarget};
+
+// Exposes /proc/<pid>/smaps_rollup for matched processes as:
+//   proc_mem_bytes{proc="<comm>", pid="<pid>", key="pss|uss|swap|anon_huge_pages|locked"} <bytes>
+// uss is Private_Clean + Private_Dirty.
+const SMAPS_KEYS: &[&str] = &["pss", "uss", "swap", "anon_huge_pages", "locked"];
+
+pub struct ProcSmapsCollector {
+    bytes: GaugeVec,
+    denied: DeniedPids,
+}
+
+impl ProcSmapsCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let bytes = GaugeVec::new(
+            Opts::new(
+                "proc_mem_bytes",
+                "Memory breakdown from /proc/<pid>/smaps_rollup, in bytes",
+            ),
+            &labels.names(&["key"]),
+        )?;
+        registry.register(Box::new(bytes.clone()))?;
+
+        Ok(Self {
+            bytes,
+            denied: DeniedPids::default(),
+        })
+    }
+
+    fn parse_rollup(content: &str) -> Vec<(&'static str, u64)> {
+        let mut private_clean = 0u64;
+        let mut private_dirty = 0u64;
+        let mut out = Vec::with_capacity(5);
+
+        // skip header line ("<start>-<end> ---p ... [rollup]")
+        for line in content.lines().skip(1) {
+            let Some((k, rest)) = line.split_once(':') else {
+                continue;
+            };
+            // e.g. "Pss:                 493 kB"
+            let Some(kb) = rest.split_whitespace().next().and_then(|v| v.parse::<u64>().ok()) else {
+                continue;
+            };
+            let bytes = kb * 1024;
+
+            match k.trim() {
+                "Pss" => out.push(("pss", bytes)),
+                "Swap" => out.push(("swap", bytes)),
+                "AnonHugePages" => out.push(("anon_huge_pages", bytes)),
+                "Locked" => out.push(("locked", bytes)),
+                "Private_Clean" => private_clean = bytes,
+                "Private_Dirty" => private_dirty = bytes,
+                _ => {}
+            }
+        }
+
+        out.push(("uss", private_clean + private_dirty));
+        out
+    }
+}
+
+impl ProcCollector for ProcSmapsCollector {
+    fn name(&self) -> &'static str {
+        "proc_smaps"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let path = format!("/proc/{pid}/smaps_rollup");
+        let Some(content) = self.denied.read("proc_smaps", pid, &path)? else {
+            return Ok(());
+        };
+
+        for (key, val) in Self::parse_rollup(&content) {
+            self.bytes.with_label_values(&target.labels(&[key])).set(val as f64);
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for key in SMAPS_KEYS {
+            let _ = self.bytes.remove_label_values(&target.labels(&[key]));
+        }
+        self.denied.forget(target.pid);
+    }
+}
+
+// Exposes /proc/<pid>/numa_maps summed over all mappings as:
+//   proc_numa_bytes{proc="<comm>", pid="<pid>", node="<N>"} <bytes>
+// Page counts are scaled by each mapping's kernelpagesize_kB, so huge page mappings add up.
+pub struct ProcNumaCollector {
+    bytes: GaugeVec,
+    denied: DeniedPids,
+    // every node label set so far, to drop series of exited PIDs
+    nodes: BTreeSet<String>,
+}
+
+impl ProcNumaCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let bytes = GaugeVec::new(
+            Opts::new(
+                "proc_numa_bytes",
+                "Bytes per NUMA node from /proc/<pid>/numa_maps, summed across mappings",
+            ),
+            &labels.names(&["node"]),
+        )?;
+        registry.register(Box::new(bytes.clone()))?;
+
+        Ok(Self {
+            bytes,
+            denied: DeniedPids::default(),
+            nodes: BTreeSet::new(),
+        })
+    }
+
+    fn parse_numa_maps(content: &str) -> BTreeMap<&str, u64> {
+        let mut per_node = BTreeMap::new();
+
+        // e.g. "55d6f37b0000 default file=/usr/bin/head mapped=2 N0=2 kernelpagesize_kB=4"
+        for line in content.lines() {
+            let page_kb = line
+                .split_whitespace()
+                .find_map(|t| t.strip_prefix("kernelpagesize_kB="))
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(4);
+            for tok in line.split_whitespace() {
+                let Some((node, pages)) = tok.strip_prefix('N').and_then(|t| t.split_once('=')) else {
+                    continue;
+                };
+                if node.is_empty() || !node.bytes().all(|b| b.is_ascii_digit()) {
+                    continue;
+                }
+                if let Ok(n) = pages.parse::<u64>() {
+                    *per_node.entry(node).or_insert(0) += n * page_kb * 1024;
+                }
+            }
+        }
+
+        per_node
+    }
+}
+
+impl ProcCollector for ProcNumaCollector {
+    fn name(&self) -> &'static str {
+        "proc_numa"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let path = format!("/proc/{pid}/numa_maps");
+        let Some(content) = self.denied.read("proc_numa", pid, &path)? else {
+            return Ok(());
+        };
+
+        for (node, bytes) in Self::parse_numa_maps(&content) {
+            if !self.nodes.contains(node) {
+                self.nodes.insert(node.to_string());
+            }
+            self.bytes.with_label_values(&target.labels(&[node])).set(bytes as f64);
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for node in &self.nodes {
+            let _ = self.bytes.remove_label_values(&target.labels(&[node.as_str()]));
+        }
+        self.denied.forget(target.pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numa_maps_scaled_by_page_size() {
+        let content = "\
+55d6f37b0000 default file=/usr/bin/head mapped=2 N0=2 kernelpagesize_kB=4
+7f0000000000 bind:1 anon=3 dirty=3 N1=3 kernelpagesize_kB=2048
+7f1000000000 default anon=1 dirty=1 N0=1 N1=1 kernelpagesize_kB=4
+";
+        let nodes = ProcNumaCollector::parse_numa_maps(content);
+        assert_eq!(nodes["0"], 3 * 4096);
+        assert_eq!(nodes["1"], 3 * 2048 * 1024 + 4096);
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File},
+    io::{self, ErrorKind, Read},
+    os::fd::{FromRawFd, OwnedFd},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitors::proc::{PidFiles, ProcCollector, ProcLabels, ProcTarget};
+
+// Exposes software perf events of matched processes as:
+//   proc_perf{proc, pid, event="task_clock|context_switches|cpu_migrations|page_faults|major_faults"} <value>
+// Counters are opened with perf_event_open(2) on every thread of the process when it is first
+// matched, with inherit set so threads and children created later are counted too, and read on
+// each tick. task_clock is exported in seconds.
+//
+// When perf_event_paranoid (or a missing CAP_PERFMON) refuses kernel-side counting we retry
+// user-only and mark the PID in proc_perf_user_only. Context switches and migrations only
+// happen in kernel context, so they are not exported for such PIDs rather than reading 0.
+// If user-only counting is refused as well the PID is marked in proc_perf_access_denied and
+// the /proc-derived metrics remain the only source for it.
+//
+// Every thread holds one fd per event, so counters may use at most half of the soft
+// RLIMIT_NOFILE; a process that would exceed that gets no counters and is marked in
+// proc_perf_fd_limited until enough fds are freed by exited processes.
+
+// PERF_TYPE_SOFTWARE and PERF_COUNT_SW_* from linux/perf_event.h;
+// the flag marks events that only fire in kernel context
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const EVENTS: &[(&str, u64, bool)] = &[
+    ("task_clock", 1, false),
+    ("context_switches", 3, true),
+    ("cpu_migrations", 4, true),
+    ("page_faults", 2, false),
+    ("major_faults", 6, false),
+];
+
+// perf_event_attr.flags bits
+const FLAG_INHERIT: u64 = 1 << 1;
+const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// First version (PERF_ATTR_SIZE_VER0) of struct perf_event_attr, which is all we need.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+struct PidCounters {
+    // counting excludes the kernel, see the kernel-only flag in EVENTS
+    user_only: bool,
+    // one set of counters per thread that existed when the PID was first seen,
+    // in EVENTS order, None for events that cannot be counted in this mode
+    threads: Vec<Vec<Option<File>>>,
+}
+
+impl PidCounters {
+    fn fds(&self) -> usize {
+        self.threads.iter().flatten().flatten().count()
+    }
+}
+
+pub struct ProcPerfCollector {
+    perf: GaugeVec,
+    access_denied: GaugeVec,
+    user_only: GaugeVec,
+    fd_limited: GaugeVec,
+    counters: HashMap<u32, PidCounters>,
+    // perf fds held by `counters`, and how many they may hold
+    open_fds: usize,
+    max_fds: usize,
+    limit_warned: bool,
+    // cleared once perf_event_open turned out to be unsupported
+    supported: bool,
+    warned: bool,
+}
+
+impl ProcPerfCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let perf = GaugeVec::new(
+            Opts::new(
+                "proc_perf",
+                "Software perf events of the process and its threads (task_clock in seconds)",
+            ),
+            &labels.names(&["event"]),
+        )?;
+        registry.register(Box::new(perf.clone()))?;
+
+        let access_denied = GaugeVec::new(
+            Opts::new(
+                "proc_perf_access_denied",
+                "1 if perf_event_open was refused for the process (see kernel.perf_event_paranoid)",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(access_denied.clone()))?;
+
+        let user_only = GaugeVec::new(
+            Opts::new(
+                "proc_perf_user_only",
+                "1 if the process's perf counters exclude the kernel (context switches and migrations not counted)",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(user_only.clone()))?;
+
+        let fd_limited = GaugeVec::new(
+            Opts::new(
+                "proc_perf_fd_limited",
+                "1 if no perf counters were opened for the process because they would exceed half of RLIMIT_NOFILE",
+            ),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(fd_limited.clone()))?;
+
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: rlim is a valid rlimit to write into
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } < 0 {
+            return Err(io::Error::last_os_error()).context("getrlimit(RLIMIT_NOFILE)");
+        }
+
+        Ok(Self {
+            perf,
+            access_denied,
+            user_only,
+            fd_limited,
+            counters: HashMap::new(),
+            open_fds: 0,
+            max_fds: (rlim.rlim_cur / 2).min(usize::MAX as u64) as usize,
+            limit_warned: false,
+            supported: true,
+            warned: false,
+        })
+    }
+
+    fn perf_event_open(tid: u32, config: u64, flags: u64) -> io::Result<File> {
+        let attr = PerfEventAttr {
+            type_: PERF_TYPE_SOFTWARE,
+            size: size_of::<PerfEventAttr>() as u32,
+            config,
+            flags,
+            ..Default::default()
+        };
+        // SAFETY: attr is a valid perf_event_attr of the size it declares
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                tid as libc::pid_t,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd was just returned by perf_event_open and is owned by nobody else
+        Ok(File::from(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) }))
+    }
+
+    /// Opens all events on `tid`; in user-only mode the kernel-only events are skipped.
+    fn open_thread(tid: u32, user_only: bool) -> io::Result<Vec<Option<File>>> {
+        let flags = if user_only {
+            FLAG_INHERIT | FLAG_EXCLUDE_HV | FLAG_EXCLUDE_KERNEL
+        } else {
+            FLAG_INHERIT | FLAG_EXCLUDE_HV
+        };
+        EVENTS
+            .iter()
+            .map(|(_, config, kernel_only)| {
+                if user_only && *kernel_only {
+                    return Ok(None);
+                }
+                match Self::perf_event_open(tid, *config, flags) {
+                    // kernels that reject exclude_kernel for the clock events
+                    Err(e) if user_only && e.raw_os_error() == Some(libc::EINVAL) => Ok(None),
+                    res => res.map(Some),
+                }
+            })
+            .collect()
+    }
+
+    /// Opens the counters of every thread, falling back to user-space only counting.
+    fn open_process(pid: u32) -> io::Result<PidCounters> {
+        match Self::open_threads(pid, false) {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => Ok(PidCounters {
+                user_only: true,
+                threads: Self::open_threads(pid, true)?,
+            }),
+            res => res.map(|threads| PidCounters {
+                user_only: false,
+                threads,
+            }),
+        }
+    }
+
+    fn open_threads(pid: u32, user_only: bool) -> io::Result<Vec<Vec<Option<File>>>> {
+        let mut threads = Vec::new();
+        for entry in fs::read_dir(format!("/proc/{pid}/task"))?.flatten() {
+            let Ok(tid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            match Self::open_thread(tid, user_only) {
+                Ok(fds) => threads.push(fds),
+                // the thread exited since the directory was listed
+                Err(e) if e.raw_os_error() == Some(libc::ESRCH) && tid != pid => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(threads)
+    }
+
+    fn read_counter(mut file: &File) -> io::Result<u64> {
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        Ok(u64::from_ne_bytes(buf))
+    }
+}
+
+impl ProcCollector for ProcPerfCollector {
+    fn name(&self) -> &'static str {
+        "proc_perf"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        if !self.supported {
+            return Ok(());
+        }
+        let pid = target.pid;
+
+        if let Entry::Vacant(slot) = self.counters.entry(pid) {
+            // threads started between this count and the open can overshoot the budget a little
+            let threads = fs::read_dir(format!("/proc/{pid}/task"))
+                .with_context(|| format!("listing threads of pid {pid}"))?
+                .count();
+            if self.open_fds + threads * EVENTS.len() > self.max_fds {
+                if !self.limit_warned {
+                    warn!(
+                        "proc_perf: {} perf fds open, not opening {} more for pid {pid} ({} threads); \
+                         limit is half of RLIMIT_NOFILE ({})",
+                        self.open_fds,
+                        threads * EVENTS.len(),
+                        threads,
+                        self.max_fds * 2
+                    );
+                    self.limit_warned = true;
+                }
+                self.fd_limited.with_label_values(&target.labels(&[])).set(1.0);
+                return Ok(());
+            }
+            match Self::open_process(pid) {
+                Ok(counters) => {
+                    self.open_fds += counters.fds();
+                    slot.insert(counters);
+                }
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                    if !self.warned {
+                        let paranoid = fs::read_to_string("/proc/sys/kernel/perf_event_paranoid").unwrap_or_default();
+                        warn!(
+                            "proc_perf: perf_event_open refused for pid {pid} (perf_event_paranoid={}), \
+                             relying on /proc only for such processes",
+                            paranoid.trim()
+                        );
+                        self.warned = true;
+                    }
+                    self.access_denied.with_label_values(&target.labels(&[])).set(1.0);
+                    return Ok(());
+                }
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EOPNOTSUPP)) => {
+                    warn!("proc_perf: software perf events not supported by this kernel, disabling: {e}");
+                    self.supported = false;
+                    return Ok(());
+                }
+                Err(e) => return Err(e).with_context(|| format!("perf_event_open for pid {pid}")),
+            }
+        }
+        let counters = &self.counters[&pid];
+        self.access_denied.with_label_values(&target.labels(&[])).set(0.0);
+        self.fd_limited.with_label_values(&target.labels(&[])).set(0.0);
+        self.user_only
+            .with_label_values(&target.labels(&[]))
+            .set(if counters.user_only { 1.0 } else { 0.0 });
+
+        let mut totals: [Option<u64>; EVENTS.len()] = [None; EVENTS.len()];
+        for fds in &counters.threads {
+            for (total, fd) in totals.iter_mut().zip(fds) {
+                if let Some(fd) = fd {
+                    let v = Self::read_counter(fd).with_context(|| format!("reading perf counter of pid {pid}"))?;
+                    *total = Some(total.unwrap_or(0) + v);
+                }
+            }
+        }
+        for ((event, ..), total) in EVENTS.iter().zip(totals) {
+            let Some(total) = total else {
+                continue;
+            };
+            let v = if *event == "task_clock" {
+                total as f64 / 1e9
+            } else {
+                total as f64
+            };
+            self.perf.with_label_values(&target.labels(&[event])).set(v);
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for (event, ..) in EVENTS {
+            let _ = self.perf.remove_label_values(&target.labels(&[event]));
+        }
+        let _ = self.access_denied.remove_label_values(&target.labels(&[]));
+        let _ = self.user_only.remove_label_values(&target.labels(&[]));
+        let _ = self.fd_limited.remove_label_values(&target.labels(&[]));
+        // dropping the files closes the counters
+        if let Some(counters) = self.counters.remove(&target.pid) {
+            self.open_fds -= counters.fds();
+        }
+    }
+}
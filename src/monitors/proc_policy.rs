@@ -0,0 +1,213 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs, io, mem,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use prometheus::{GaugeVec, Opts, Registry};
+use serde::Deserialize;
+
+use crate::monitors::proc::{read_stat, PidFiles, ProcCollector, ProcLabels, ProcTarget};
+
+// Checks matched processes against the scheduling they are supposed to run with and exposes:
+//   proc_sched_policy_violation{proc, pid, check="policy|rt_priority|cpus|mem_nodes"} 0|1
+// Expectations are declared per --proc-name selector in a JSON file, e.g.
+//   { "pinger": { "policy": "fifo", "rt_priority": 50, "cpus": "2-3", "mem_nodes": "0" } }
+// cpus and mem_nodes are satisfied when the actual affinity / Mems_allowed is a subset.
+
+const CHECKS: &[&str] = &["policy", "rt_priority", "cpus", "mem_nodes"];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicySpec {
+    policy: Option<String>,
+    rt_priority: Option<u32>,
+    cpus: Option<String>,
+    mem_nodes: Option<String>,
+}
+
+#[derive(Debug)]
+struct Expected {
+    policy: Option<u32>,
+    rt_priority: Option<u32>,
+    cpus: Option<BTreeSet<u32>>,
+    mem_nodes: Option<BTreeSet<u32>>,
+}
+
+pub struct ProcPolicyCollector {
+    expected: HashMap<String, Expected>,
+    violation: GaugeVec,
+    // (pid, check) pairs currently in violation, so drift is logged once when it starts
+    violating: HashSet<(u32, &'static str)>,
+}
+
+impl ProcPolicyCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels, path: &Path, selectors: &[String]) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+        let specs: HashMap<String, PolicySpec> =
+            serde_json::from_str(&content).with_context(|| format!("parsing {path:?}"))?;
+
+        let mut expected = HashMap::new();
+        for (selector, spec) in specs {
+            if !selectors.contains(&selector) {
+                warn!("proc_policy: '{selector}' in {path:?} is not a --proc-name selector, ignoring");
+                continue;
+            }
+            let e = Expected {
+                policy: spec.policy.as_deref().map(parse_policy).transpose()?,
+                rt_priority: spec.rt_priority,
+                cpus: spec.cpus.as_deref().map(parse_list).transpose()?,
+                mem_nodes: spec.mem_nodes.as_deref().map(parse_list).transpose()?,
+            };
+            info!("proc_policy: expecting {e:?} for selector '{selector}'");
+            expected.insert(selector, e);
+        }
+
+        let violation = GaugeVec::new(
+            Opts::new(
+                "proc_sched_policy_violation",
+                "1 if the process does not run with the declared scheduling policy, priority, CPUs or memory nodes",
+            ),
+            &labels.names(&["check"]),
+        )?;
+        registry.register(Box::new(violation.clone()))?;
+
+        Ok(Self {
+            expected,
+            violation,
+            violating: HashSet::new(),
+        })
+    }
+
+    fn affinity(pid: u32) -> Result<BTreeSet<u32>> {
+        // SAFETY: cpu_set_t is plain old data, all-zero is an empty set
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        // SAFETY: set is a valid cpu_set_t of the given size
+        let rc = unsafe { libc::sched_getaffinity(pid as libc::pid_t, mem::size_of::<libc::cpu_set_t>(), &mut set) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| format!("sched_getaffinity({pid})"));
+        }
+        Ok((0..libc::CPU_SETSIZE as u32)
+            // SAFETY: cpu is below CPU_SETSIZE
+            .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu as usize, &set) })
+            .collect())
+    }
+
+    fn mems_allowed(pid: u32) -> Result<BTreeSet<u32>> {
+        let path = format!("/proc/{pid}/status");
+        let status = fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+        let list = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Mems_allowed_list:"))
+            .with_context(|| format!("missing Mems_allowed_list in {path}"))?;
+        parse_list(list.trim())
+    }
+
+    fn set(&mut self, target: &ProcTarget, check: &'static str, ok: bool, detail: impl FnOnce() -> String) {
+        self.violation
+            .with_label_values(&target.labels(&[check]))
+            .set(if ok { 0.0 } else { 1.0 });
+
+        let key = (target.pid, check);
+        if ok {
+            if self.violating.remove(&key) {
+                info!(
+                    "proc_policy: pid {} (comm={}) {check} back in compliance",
+                    target.pid, target.comm
+                );
+            }
+        } else if self.violating.insert(key) {
+            warn!(
+                "proc_policy: pid {} (comm={}) {check} drifted: {}",
+                target.pid,
+                target.comm,
+                detail()
+            );
+        }
+    }
+}
+
+impl ProcCollector for ProcPolicyCollector {
+    fn name(&self) -> &'static str {
+        "proc_policy"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, _files: &mut PidFiles) -> Result<()> {
+        let Some(exp) = self.expected.get(&target.selector) else {
+            return Ok(());
+        };
+        let (policy, rt_priority, cpus, mem_nodes) =
+            (exp.policy, exp.rt_priority, exp.cpus.clone(), exp.mem_nodes.clone());
+        let pid = target.pid;
+
+        if policy.is_some() || rt_priority.is_some() {
+            let st = read_stat(pid)?;
+            if let Some(want) = policy {
+                self.set(target, "policy", st.policy == want, || {
+                    format!("policy {} (want {want})", st.policy)
+                });
+            }
+            if let Some(want) = rt_priority {
+                self.set(target, "rt_priority", st.rt_priority == want, || {
+                    format!("rt_priority {} (want {want})", st.rt_priority)
+                });
+            }
+        }
+        if let Some(want) = cpus {
+            let actual = Self::affinity(pid)?;
+            self.set(target, "cpus", actual.is_subset(&want), || {
+                format!("affinity {actual:?} (allowed {want:?})")
+            });
+        }
+        if let Some(want) = mem_nodes {
+            let actual = Self::mems_allowed(pid)?;
+            self.set(target, "mem_nodes", actual.is_subset(&want), || {
+                format!("mems_allowed {actual:?} (allowed {want:?})")
+            });
+        }
+
+        Ok(())
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for check in CHECKS {
+            let _ = self.violation.remove_label_values(&target.labels(&[check]));
+            self.violating.remove(&(target.pid, *check));
+        }
+    }
+}
+
+fn parse_policy(s: &str) -> Result<u32> {
+    let name = s.to_ascii_lowercase();
+    Ok(match name.strip_prefix("sched_").unwrap_or(&name) {
+        "other" | "normal" => libc::SCHED_OTHER as u32,
+        "fifo" => libc::SCHED_FIFO as u32,
+        "rr" => libc::SCHED_RR as u32,
+        "batch" => libc::SCHED_BATCH as u32,
+        "idle" => libc::SCHED_IDLE as u32,
+        "deadline" => 6,
+        _ => bail!("unknown scheduling policy '{s}'"),
+    })
+}
+
+/// Parses a kernel list format such as "0-3,8,10-11".
+fn parse_list(s: &str) -> Result<BTreeSet<u32>> {
+    let mut out = BTreeSet::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((a, b)) => {
+                let (a, b): (u32, u32) = (
+                    a.parse().with_context(|| format!("bad list '{s}'"))?,
+                    b.parse().with_context(|| format!("bad list '{s}'"))?,
+                );
+                out.extend(a..=b);
+            }
+            None => {
+                out.insert(part.parse().with_context(|| format!("bad list '{s}'"))?);
+            }
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use prometheus::{exponential_buckets, CounterVec, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+
+use crate::monitors::proc::{read_task_stat, ProcLabels, ProcTarget};
+
+// Samples the state and wait channel of every thread of the matched processes at a fixed
+// rate, as a cheap off-CPU profile, and exposes per process:
+//   proc_thread_state_seconds_total{proc, pid, state="R|S|D|..."}   sampled time in each state
+//   proc_thread_state_duration_seconds{proc, pid, state}             histogram of contiguous stretches
+//   proc_thread_wchan_samples{proc, pid, wchan}                      top wait channels (S/D samples)
+// The wait channel comes from /proc/<pid>/task/<tid>/wchan, falling back to the first frame of
+// /proc/<pid>/task/<tid>/stack when wchan is hidden and we have permission to read the stack.
+
+const TOP_WCHANS: usize = 10;
+// how often the top wait channels and the thread lists are refreshed
+const PUBLISH_EVERY: Duration = Duration::from_secs(1);
+
+struct ThreadState {
+    state: char,
+    since: Instant,
+}
+
+struct ProcessSamples {
+    target: ProcTarget,
+    tids: Vec<u32>,
+    threads: HashMap<u32, ThreadState>,
+    wchans: HashMap<String, u64>,
+    // wchan labels currently exported
+    exported: Vec<String>,
+}
+
+pub struct ThreadSampler {
+    period: Duration,
+    targets: Arc<Mutex<Vec<ProcTarget>>>,
+    procs: HashMap<u32, ProcessSamples>,
+    // cleared once reading a stack was refused, so we stop trying
+    stack_readable: bool,
+    state_seconds: CounterVec,
+    state_duration: HistogramVec,
+    wchan_samples: GaugeVec,
+}
+
+impl ThreadSampler {
+    /// Starts sampling the processes published in `targets` at `hz` samples per second.
+    pub fn spawn(registry: &Registry, labels: ProcLabels, targets: Arc<Mutex<Vec<ProcTarget>>>, hz: u32) -> Result<()> {
+        let state_seconds = CounterVec::new(
+            Opts::new(
+                "proc_thread_state_seconds_total",
+                "Sampled time threads of the process spent in each /proc/<pid>/task/<tid>/stat state",
+            ),
+            &labels.names(&["state"]),
+        )?;
+        registry.register(Box::new(state_seconds.clone()))?;
+
+        let period = Duration::from_secs_f64(1.0 / hz.max(1) as f64);
+        let state_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "proc_thread_state_duration_seconds",
+                "Length of contiguous stretches a thread stayed in one state, as seen by the sampler",
+            )
+            .buckets(exponential_buckets(period.as_secs_f64(), 2.0, 14)?),
+            &labels.names(&["state"]),
+        )?;
+        registry.register(Box::new(state_duration.clone()))?;
+
+        let wchan_samples = GaugeVec::new(
+            Opts::new(
+                "proc_thread_wchan_samples",
+                "Samples of sleeping threads per wait channel (top channels per process)",
+            ),
+            &labels.names(&["wchan"]),
+        )?;
+        registry.register(Box::new(wchan_samples.clone()))?;
+
+        let mut sampler = Self {
+            period,
+            targets,
+            procs: HashMap::new(),
+            stack_readable: true,
+            state_seconds,
+            state_duration,
+            wchan_samples,
+        };
+
+        thread::Builder::new()
+            .name("proc-sampler".to_string())
+            .spawn(move || sampler.run())
+            .context("spawning thread sampler")?;
+
+        info!("proc_sampler: sampling thread states at {hz} Hz");
+        Ok(())
+    }
+
+    fn run(&mut self) {
+        let mut next = Instant::now();
+        let mut last_publish = Instant::now();
+        loop {
+            if last_publish.elapsed() >= PUBLISH_EVERY {
+                self.publish();
+                self.refresh();
+                last_publish = Instant::now();
+            }
+
+            self.sample();
+
+            next += self.period;
+            match next.checked_duration_since(Instant::now()) {
+                Some(d) => thread::sleep(d),
+                // fell behind, don't try to catch up with a burst of samples
+                None => next = Instant::now(),
+            }
+        }
+    }
+
+    /// Picks up the latest matched processes and their thread lists.
+    fn refresh(&mut self) {
+        let targets = self.targets.lock().unwrap().clone();
+
+        let gone: Vec<u32> = self
+            .procs
+            .keys()
+            .filter(|pid| !targets.iter().any(|t| t.pid == **pid))
+            .copied()
+            .collect();
+        for pid in gone {
+            if let Some(p) = self.procs.remove(&pid) {
+                self.forget(&p);
+            }
+        }
+
+        for target in targets {
+            let tids = Self::list_tids(target.pid);
+            match self.procs.get_mut(&target.pid) {
+                Some(p) if p.target == target => {
+                    p.tids = tids;
+                    p.threads.retain(|tid, _| p.tids.contains(tid));
+                }
+                _ => {
+                    if let Some(old) = self.procs.remove(&target.pid) {
+                        self.forget(&old);
+                    }
+                    self.procs.insert(
+                        target.pid,
+                        ProcessSamples {
+                            target,
+                            tids,
+                            threads: HashMap::new(),
+                            wchans: HashMap::new(),
+                            exported: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn list_tids(pid: u32) -> Vec<u32> {
+        let Ok(entries) = fs::read_dir(format!("/proc/{pid}/task")) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|e| e.file_name().to_string_lossy().parse().ok())
+            .collect()
+    }
+
+    fn sample(&mut self) {
+        let now = Instant::now();
+        let period = self.period.as_secs_f64();
+
+        for p in self.procs.values_mut() {
+            let pid = p.target.pid;
+            for tid in &p.tids {
+                // threads exit between refreshes; they are dropped on the next one
+                let Ok(st) = read_task_stat(pid, *tid) else {
+                    continue;
+                };
+                let state_s = st.state.to_string();
+                self.state_seconds
+                    .with_label_values(&p.target.labels(&[state_s.as_str()]))
+                    .inc_by(period);
+
+                match p.threads.get_mut(tid) {
+                    Some(t) if t.state == st.state => {}
+                    Some(t) => {
+                        let prev_s = t.state.to_string();
+                        self.state_duration
+                            .with_label_values(&p.target.labels(&[prev_s.as_str()]))
+                            .observe(now.duration_since(t.since).as_secs_f64());
+                        *t = ThreadState {
+                            state: st.state,
+                            since: now,
+                        };
+                    }
+                    None => {
+                        p.threads.insert(
+                            *tid,
+                            ThreadState {
+                                state: st.state,
+                                since: now,
+                            },
+                        );
+                    }
+                }
+
+                if matches!(st.state, 'S' | 'D') {
+                    if let Some(wchan) = Self::wait_channel(pid, *tid, &mut self.stack_readable) {
+                        *p.wchans.entry(wchan).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn wait_channel(pid: u32, tid: u32, stack_readable: &mut bool) -> Option<String> {
+        let wchan = fs::read_to_string(format!("/proc/{pid}/task/{tid}/wchan")).unwrap_or_default();
+        let wchan = wchan.trim();
+        if !wchan.is_empty() && wchan != "0" {
+            return Some(wchan.to_string());
+        }
+        if !*stack_readable {
+            return None;
+        }
+
+        // e.g. "[<0>] do_epoll_wait+0x4a7/0x4f0"
+        match fs::read_to_string(format!("/proc/{pid}/task/{tid}/stack")) {
+            Ok(stack) => {
+                let frame = stack.lines().next()?.split_whitespace().nth(1)?;
+                Some(frame.split('+').next().unwrap_or(frame).to_string())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                debug!("proc_sampler: kernel stacks not readable, using wchan only: {e}");
+                *stack_readable = false;
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Exports the top wait channels of each process.
+    fn publish(&mut self) {
+        for p in self.procs.values_mut() {
+            let mut top: Vec<(&String, &u64)> = p.wchans.iter().collect();
+            top.sort_by(|a, b| b.1.cmp(a.1));
+            top.truncate(TOP_WCHANS);
+
+            let exported: Vec<String> = top.iter().map(|(w, _)| (*w).clone()).collect();
+            for old in p.exported.iter().filter(|w| !exported.contains(w)) {
+                let _ = self
+                    .wchan_samples
+                    .remove_label_values(&p.target.labels(&[old.as_str()]));
+            }
+            for (wchan, n) in top {
+                self.wchan_samples
+                    .with_label_values(&p.target.labels(&[wchan.as_str()]))
+                    .set(*n as f64);
+            }
+            p.exported = exported;
+        }
+    }
+
+    fn forget(&self, p: &ProcessSamples) {
+        for wchan in &p.exported {
+            let _ = self
+                .wchan_samples
+                .remove_label_values(&p.target.labels(&[wchan.as_str()]));
+        }
+        for state in ["R", "S", "D", "T", "t", "Z", "X", "I", "P", "W", "K"] {
+            let _ = self.state_seconds.remove_label_values(&p.target.labels(&[state]));
+            let _ = self.state_duration.remove_label_values(&p.target.labels(&[state]));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs,
+    os::unix::fs::MetadataExt,
+};
+
+use anyhow::{Context, Result};
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitors::proc::{DeniedPids, PidFiles, ProcCollector, ProcLabels, ProcTarget};
+use crate::monitors::socktable::{read_socket_table, TCP_STATES};
+
+// Exposes the socket inventory of matched processes as:
+//   proc_tcp_sockets{proc, pid, state="established|listen|..."}   <count>
+//   proc_udp_sockets{proc, pid}                                  <count>
+//   proc_socket_queue_bytes{proc, pid, proto="tcp|udp", queue="rx|tx"}  <summed queue bytes>
+// Socket inodes from /proc/<pid>/fd are looked up in /proc/<pid>/net/{tcp,tcp6,udp,udp6},
+// which are the tables of the process's own network namespace. Each namespace's tables are
+// parsed once per tick, keyed by the /proc/<pid>/ns/net inode.
+
+const QUEUES: &[(&str, &str)] = &[("tcp", "rx"), ("tcp", "tx"), ("udp", "rx"), ("udp", "tx")];
+
+/// A socket of the namespace tables, by inode.
+struct NetnsSocket {
+    tcp: bool,
+    state: usize,
+    rx_queue: u64,
+    tx_queue: u64,
+}
+
+pub struct ProcSocketsCollector {
+    tcp: GaugeVec,
+    udp: GaugeVec,
+    queues: GaugeVec,
+    denied: DeniedPids,
+    // netns inode -> socket inode -> socket, for this tick
+    netns: HashMap<u64, HashMap<u64, NetnsSocket>>,
+}
+
+impl ProcSocketsCollector {
+    pub fn new(registry: &Registry, labels: ProcLabels) -> Result<Self> {
+        let tcp = GaugeVec::new(
+            Opts::new("proc_tcp_sockets", "TCP sockets owned by the process, by state"),
+            &labels.names(&["state"]),
+        )?;
+        registry.register(Box::new(tcp.clone()))?;
+
+        let udp = GaugeVec::new(
+            Opts::new("proc_udp_sockets", "UDP sockets owned by the process"),
+            &labels.names(&[]),
+        )?;
+        registry.register(Box::new(udp.clone()))?;
+
+        let queues = GaugeVec::new(
+            Opts::new(
+                "proc_socket_queue_bytes",
+                "Bytes queued in the receive or send queues of the process's sockets",
+            ),
+            &labels.names(&["proto", "queue"]),
+        )?;
+        registry.register(Box::new(queues.clone()))?;
+
+        Ok(Self {
+            tcp,
+            udp,
+            queues,
+            denied: DeniedPids::default(),
+            netns: HashMap::new(),
+        })
+    }
+
+    /// Sockets of the network namespace of `pid`, read through its /proc/<pid>/net tables.
+    fn netns_sockets(&mut self, pid: u32) -> Result<&HashMap<u64, NetnsSocket>> {
+        let path = format!("/proc/{pid}/ns/net");
+        let ns = fs::metadata(&path).with_context(|| format!("reading {path}"))?.ino();
+        let entry = match self.netns.entry(ns) {
+            Entry::Occupied(e) => return Ok(e.into_mut()),
+            Entry::Vacant(e) => e,
+        };
+        let mut sockets = HashMap::new();
+        for table in ["tcp", "tcp6", "udp", "udp6"] {
+            for s in read_socket_table(&format!("/proc/{pid}/net/{table}"))? {
+                sockets.insert(
+                    s.inode,
+                    NetnsSocket {
+                        tcp: table.starts_with("tcp"),
+                        state: s.state,
+                        rx_queue: s.rx_queue,
+                        tx_queue: s.tx_queue,
+                    },
+                );
+            }
+        }
+        Ok(entry.insert(sockets))
+    }
+
+    /// Socket inodes of the process, or `None` if the fd directory is not accessible.
+    fn socket_inodes(&mut self, files: &mut PidFiles) -> Result<Option<HashSet<u64>>> {
+        let Some(targets) = self.denied.fd_targets("proc_sock", files)? else {
+            return Ok(None);
+        };
+        // e.g. "socket:[1234]"
+        let inodes = targets
+            .iter()
+            .filter_map(|t| t.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok())
+            .collect();
+        Ok(Some(inodes))
+    }
+}
+
+impl ProcCollector for ProcSocketsCollector {
+    fn name(&self) -> &'static str {
+        "proc_sock"
+    }
+
+    fn collect_pid(&mut self, target: &ProcTarget, files: &mut PidFiles) -> Result<()> {
+        let pid = target.pid;
+        let Some(inodes) = self.socket_inodes(files)? else {
+            return Ok(());
+        };
+
+        let mut tcp_states = vec![0u64; TCP_STATES.len()];
+        // rx/tx per proto, in QUEUES order
+        let mut queues = [0u64; 4];
+        let mut udp = 0u64;
+
+        // the tables list every socket of the namespace, skip them for socket-less processes
+        if !inodes.is_empty() {
+            let sockets = self.netns_sockets(pid)?;
+            // other fds are unix, netlink, ... sockets, not in these tables
+            for s in inodes.iter().filter_map(|i| sockets.get(i)) {
+                let q = if s.tcp { 0 } else { 2 };
+                queues[q] += s.rx_queue;
+                queues[q + 1] += s.tx_queue;
+                if !s.tcp {
+                    udp += 1;
+                } else if let Some(n) = tcp_states.get_mut(s.state) {
+                    *n += 1;
+                }
+            }
+        }
+
+        for (state, n) in TCP_STATES.iter().zip(tcp_states).skip(1) {
+            self.tcp.with_label_values(&target.labels(&[state])).set(n as f64);
+        }
+        self.udp.with_label_values(&target.labels(&[])).set(udp as f64);
+        for ((proto, queue), v) in QUEUES.iter().zip(queues) {
+            self.queues
+                .with_label_values(&target.labels(&[proto, queue]))
+                .set(v as f64);
+        }
+
+        Ok(())
+    }
+
+    fn begin_tick(&mut self) {
+        self.netns.clear();
+    }
+
+    fn forget_pid(&mut self, target: &ProcTarget) {
+        for state in TCP_STATES.iter().skip(1) {
+            let _ = self.tcp.remove_label_values(&target.labels(&[state]));
+        }
+        let _ = self.udp.remove_label_values(&target.labels(&[]));
+        for (proto, queue) in QUEUES {
+            let _ = self.queues.remove_label_values(&target.labels(&[proto, queue]));
+        }
+        self.denied.forget(target.pid);
+    }
+}
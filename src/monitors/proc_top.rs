@@ -0,0 +1,152 @@
+use std::{collections::HashMap, fs, time::Instant};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::{Monitor, TopBy};
+use crate::monitors::proc::{read_comm, read_stat, ProcessSchedMonitor, SCHED_KEYS};
+
+// Host-wide mode of the process monitor: ranks every process by the growth of one
+// counter over the last interval and exports only the top N as:
+//   proc_top_rank{proc, pid}            1..N
+//   proc_top_rate{proc, pid}            growth of the ranking counter per second
+//   proc_top_sched{proc, pid, key}      /proc/<pid>/sched values, as for --proc-name
+// PIDs that fall out of the top N are removed, so the label set stays bounded.
+
+pub struct TopProcessesMonitor {
+    n: usize,
+    by: TopBy,
+    clk_tck: f64,
+    // (pid, starttime) -> counter value on the previous tick; starttime guards against PID reuse
+    prev: HashMap<(u32, u64), f64>,
+    prev_at: Option<Instant>,
+    // (comm, pid) exported on the previous tick
+    exported: Vec<(String, String)>,
+    rank: GaugeVec,
+    rate: GaugeVec,
+    sched: GaugeVec,
+}
+
+impl TopProcessesMonitor {
+    pub fn new(registry: &Registry, n: usize, by: TopBy) -> Result<Self> {
+        let rank = GaugeVec::new(
+            Opts::new("proc_top_rank", "Rank of the process among the host-wide top N"),
+            &["proc", "pid"],
+        )?;
+        registry.register(Box::new(rank.clone()))?;
+
+        let rate = GaugeVec::new(
+            Opts::new(
+                "proc_top_rate",
+                "Per-second growth of the ranking counter (CPU seconds, involuntary switches or migrations)",
+            ),
+            &["proc", "pid"],
+        )?;
+        registry.register(Box::new(rate.clone()))?;
+
+        let sched = GaugeVec::new(
+            Opts::new("proc_top_sched", "Values from /proc/<pid>/sched of the host-wide top N"),
+            &["proc", "pid", "key"],
+        )?;
+        registry.register(Box::new(sched.clone()))?;
+
+        Ok(Self {
+            n,
+            by,
+            // SAFETY: sysconf has no preconditions
+            clk_tck: unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64,
+            prev: HashMap::new(),
+            prev_at: None,
+            exported: Vec::new(),
+            rank,
+            rate,
+            sched,
+        })
+    }
+
+    /// Current value of the ranking counter, or None if the process is gone.
+    fn counter(&self, pid: u32) -> Option<(u64, f64)> {
+        let st = read_stat(pid).ok()?;
+        let v = match self.by {
+            TopBy::Cpu => (st.utime + st.stime) as f64 / self.clk_tck,
+            TopBy::Involuntary => ProcessSchedMonitor::read_sched(pid).ok()?.nr_involuntary_switches as f64,
+            TopBy::Migrations => ProcessSchedMonitor::read_sched(pid).ok()?.nr_migrations as f64,
+        };
+        Some((st.starttime, v))
+    }
+}
+
+impl Monitor for TopProcessesMonitor {
+    fn name(&self) -> &'static &str {
+        &"proc_top"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let mut cur = HashMap::with_capacity(self.prev.len());
+        let mut deltas: Vec<(u32, f64)> = Vec::new();
+
+        for entry in fs::read_dir("/proc").context("reading /proc")?.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            let Some((starttime, v)) = self.counter(pid) else {
+                continue;
+            };
+            if let Some(prev) = self.prev.get(&(pid, starttime)) {
+                deltas.push((pid, v - prev));
+            }
+            cur.insert((pid, starttime), v);
+        }
+
+        let elapsed = self.prev_at.map(|t| now.duration_since(t).as_secs_f64());
+        self.prev = cur;
+        self.prev_at = Some(now);
+        let Some(elapsed) = elapsed.filter(|e| *e > 0.0) else {
+            return Ok(());
+        };
+
+        deltas.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut exported = Vec::with_capacity(self.n);
+        for (pid, delta) in deltas.into_iter().filter(|(_, d)| *d > 0.0) {
+            if exported.len() == self.n {
+                break;
+            }
+            let Ok(comm) = read_comm(pid) else {
+                continue;
+            };
+            let Ok(s) = ProcessSchedMonitor::read_sched(pid) else {
+                continue;
+            };
+
+            let pid_s = pid.to_string();
+            let labels = [comm.as_str(), pid_s.as_str()];
+            self.rank.with_label_values(&labels).set((exported.len() + 1) as f64);
+            self.rate.with_label_values(&labels).set(delta / elapsed);
+
+            for (key, v) in SCHED_KEYS.iter().zip(s.values()) {
+                self.sched
+                    .with_label_values(&[comm.as_str(), pid_s.as_str(), key])
+                    .set(v);
+            }
+
+            exported.push((comm, pid_s));
+        }
+
+        // rotate out processes that are no longer in the top N
+        for (comm, pid_s) in self.exported.iter().filter(|e| !exported.contains(e)) {
+            let labels = [comm.as_str(), pid_s.as_str()];
+            let _ = self.rank.remove_label_values(&labels);
+            let _ = self.rate.remove_label_values(&labels);
+            for key in SCHED_KEYS {
+                let _ = self.sched.remove_label_values(&[comm.as_str(), pid_s.as_str(), key]);
+            }
+        }
+
+        debug!("proc_top: exported top {} processes by {:?}", exported.len(), self.by);
+        self.exported = exported;
+        Ok(())
+    }
+}
@@ -2,16 +2,43 @@ use anyhow::{Context, Result};
 use log::debug;
 use prometheus::{GaugeVec, Opts, Registry};
 use std::{
+    collections::HashSet,
     fs,
     path::{Path, PathBuf},
 };
 
 use crate::monitor::Monitor;
 
+// Per-queue values from /sys/class/net/<iface>/queues/{rx,tx}-<qid>:
+//   netdev_queue_stat{iface, qtype, qid, key}   decimal files (rps_flow_cnt, tx_maxrate, tx_timeout, ...);
+//                                               traffic_class of a subordinate device's queue is
+//                                               "<tc>-<channel>" and adds key="traffic_class_sb_channel"
+//   netdev_queue_cpu{iface, qtype, qid, cpu}    1 for each CPU in rps_cpus (rx) / xps_cpus (tx)
+//   netdev_queue_xps_rxq{iface, qid, rxq}       1 for each rx queue in xps_rxqs
+//   netdev_queue_bql{iface, qid, key}           byte_queue_limits of tx queues, times in seconds
+
+/// (key, file in byte_queue_limits, scale)
+const BQL_KEYS: &[(&str, &str, f64)] = &[
+    ("limit_bytes", "limit", 1.0),
+    ("limit_max_bytes", "limit_max", 1.0),
+    ("limit_min_bytes", "limit_min", 1.0),
+    ("inflight_bytes", "inflight", 1.0),
+    ("hold_time_seconds", "hold_time", 1e-3),
+    ("stall_count", "stall_cnt", 1.0),
+    ("stall_max_seconds", "stall_max", 1e-3),
+    ("stall_threshold_seconds", "stall_thrs", 1e-3),
+];
+
 pub struct NetSysfsQueuesMonitor {
     root: PathBuf,
     metrics: GaugeVec,
+    cpus: GaugeVec,
+    rxqs: GaugeVec,
+    bql: GaugeVec,
     include_lo: bool,
+    // mask bits set on the previous tick, to drop CPUs or queues removed from a mask
+    exported_cpus: HashSet<[String; 4]>,
+    exported_rxqs: HashSet<[String; 3]>,
 }
 
 impl NetSysfsQueuesMonitor {
@@ -25,10 +52,42 @@ impl NetSysfsQueuesMonitor {
         )?;
         registry.register(Box::new(metrics.clone()))?;
 
+        let cpus = GaugeVec::new(
+            Opts::new(
+                "netdev_queue_cpu",
+                "1 for each CPU steering the queue, from rps_cpus (rx) and xps_cpus (tx)",
+            ),
+            &["iface", "qtype", "qid", "cpu"],
+        )?;
+        registry.register(Box::new(cpus.clone()))?;
+
+        let rxqs = GaugeVec::new(
+            Opts::new(
+                "netdev_queue_xps_rxq",
+                "1 for each rx queue mapped to the tx queue in xps_rxqs",
+            ),
+            &["iface", "qid", "rxq"],
+        )?;
+        registry.register(Box::new(rxqs.clone()))?;
+
+        let bql = GaugeVec::new(
+            Opts::new(
+                "netdev_queue_bql",
+                "Byte queue limits of tx queues from queues/tx-<qid>/byte_queue_limits (times in seconds)",
+            ),
+            &["iface", "qid", "key"],
+        )?;
+        registry.register(Box::new(bql.clone()))?;
+
         Ok(Self {
             root: PathBuf::from("/sys/class/net"),
             metrics,
+            cpus,
+            rxqs,
+            bql,
             include_lo: false,
+            exported_cpus: HashSet::new(),
+            exported_rxqs: HashSet::new(),
         })
     }
 
@@ -42,6 +101,23 @@ impl NetSysfsQueuesMonitor {
         Ok(v)
     }
 
+    /// Set bits of a mask file such as rps_cpus, or `None` if it cannot be read.
+    fn read_mask(path: &Path) -> Option<Vec<usize>> {
+        // xps_cpus is missing on single-queue devices, masks are unreadable without CONFIG_RPS/XPS
+        let s = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                debug!("net_sysfs_queues: skip {path:?}: {e}");
+                return None;
+            }
+        };
+        let bits = parse_mask(&s);
+        if bits.is_none() {
+            debug!("net_sysfs_queues: bad mask in {path:?}: {}", s.trim());
+        }
+        bits
+    }
+
     #[inline]
     fn emit_file(&self, iface: &str, qtype: &str, qid: &str, key: &str, path: &Path) {
         match Self::read_u64(path) {
@@ -56,7 +132,48 @@ impl NetSysfsQueuesMonitor {
         }
     }
 
-    fn scrape_queue_dir(&self, iface: &str, qtype: &str, qid: &str, qdir: &Path) -> Result<usize> {
+    fn emit_traffic_class(&self, iface: &str, qid: &str, path: &Path) {
+        let s = match fs::read_to_string(path) {
+            Ok(s) => s,
+            // EINVAL without a tc mapping, missing without CONFIG_XPS
+            Err(e) => {
+                debug!("net_sysfs_queues: skip {path:?}: {e}");
+                return;
+            }
+        };
+        let Some((tc, channel)) = parse_traffic_class(&s) else {
+            debug!("net_sysfs_queues: bad traffic_class in {path:?}: {}", s.trim());
+            return;
+        };
+        self.metrics
+            .with_label_values(&[iface, "tx", qid, "traffic_class"])
+            .set(tc as f64);
+        if let Some(channel) = channel {
+            self.metrics
+                .with_label_values(&[iface, "tx", qid, "traffic_class_sb_channel"])
+                .set(channel as f64);
+        }
+    }
+
+    fn emit_bql(&self, iface: &str, qid: &str, dir: &Path) {
+        for (key, file, scale) in BQL_KEYS {
+            let path = dir.join(file);
+            match Self::read_u64(&path) {
+                Ok(val) => self.bql.with_label_values(&[iface, qid, key]).set(val as f64 * scale),
+                Err(e) => debug!("net_sysfs_queues: skip {path:?}: {e:#}"),
+            }
+        }
+    }
+
+    fn scrape_queue_dir(
+        &self,
+        iface: &str,
+        qtype: &str,
+        qid: &str,
+        qdir: &Path,
+        cpus: &mut HashSet<[String; 4]>,
+        rxqs: &mut HashSet<[String; 3]>,
+    ) -> Result<usize> {
         let mut count = 0usize;
         let entries = fs::read_dir(qdir).with_context(|| format!("reading queue dir {qdir:?} ({qtype}-{qid})"))?;
 
@@ -69,40 +186,34 @@ impl NetSysfsQueuesMonitor {
             let name = entry.file_name().to_string_lossy().to_string();
 
             if ft.is_file() {
-                self.emit_file(iface, qtype, qid, &name, &path);
-                count += 1;
-            } else if ft.is_dir() {
-                let subdir = path;
-                let sub_entries = match fs::read_dir(&subdir) {
-                    Ok(it) => it,
-                    Err(e) => {
-                        debug!("net_sysfs_queues: cannot read subdir {subdir:?}: {e}");
-                        continue;
-                    }
-                };
-                for sub_res in sub_entries {
-                    let sub = match sub_res {
-                        Ok(s) => s,
-                        Err(e) => {
-                            debug!("net_sysfs_queues: iter subdir {subdir:?}: {e}");
-                            continue;
+                match name.as_str() {
+                    "rps_cpus" | "xps_cpus" => {
+                        for cpu in Self::read_mask(&path).unwrap_or_default() {
+                            let cpu = cpu.to_string();
+                            let labels = [iface, qtype, qid, cpu.as_str()].map(str::to_string);
+                            self.cpus
+                                .with_label_values(&labels.each_ref().map(String::as_str))
+                                .set(1.0);
+                            cpus.insert(labels);
                         }
-                    };
-                    let sub_path = sub.path();
-                    let sub_ft = match sub.file_type() {
-                        Ok(ft) => ft,
-                        Err(e) => {
-                            debug!("net_sysfs_queues: file_type {sub_path:?}: {e}");
-                            continue;
+                    }
+                    "xps_rxqs" => {
+                        for rxq in Self::read_mask(&path).unwrap_or_default() {
+                            let rxq = rxq.to_string();
+                            let labels = [iface, qid, rxq.as_str()].map(str::to_string);
+                            self.rxqs
+                                .with_label_values(&labels.each_ref().map(String::as_str))
+                                .set(1.0);
+                            rxqs.insert(labels);
                         }
-                    };
-                    if !sub_ft.is_file() {
-                        continue;
                     }
-                    let sub_name = format!("{}_{}", name, sub.file_name().to_string_lossy());
-                    self.emit_file(iface, qtype, qid, &sub_name, &sub_path);
-                    count += 1;
+                    "traffic_class" => self.emit_traffic_class(iface, qid, &path),
+                    _ => self.emit_file(iface, qtype, qid, &name, &path),
                 }
+                count += 1;
+            } else if ft.is_dir() && name == "byte_queue_limits" {
+                self.emit_bql(iface, qid, &path);
+                count += 1;
             } else {
                 continue;
             }
@@ -112,6 +223,26 @@ impl NetSysfsQueuesMonitor {
     }
 }
 
+/// Parses a sysfs bitmap such as "ff,00000001" (comma-separated 32-bit hex words, most
+/// significant first) into the indexes of its set bits.
+fn parse_mask(s: &str) -> Option<Vec<usize>> {
+    let mut bits = Vec::new();
+    for (word_idx, word) in s.trim().rsplit(',').enumerate() {
+        let word = u32::from_str_radix(word, 16).ok()?;
+        bits.extend((0..32).filter(|b| word & (1 << b) != 0).map(|b| word_idx * 32 + b));
+    }
+    Some(bits)
+}
+
+/// Parses traffic_class: "<tc>", or "<tc>-<channel>" for a queue of a subordinate device
+/// (the kernel prints tc followed by the negative num_tc holding the channel).
+fn parse_traffic_class(s: &str) -> Option<(u64, Option<u64>)> {
+    match s.trim().split_once('-') {
+        Some((tc, channel)) => Some((tc.parse().ok()?, Some(channel.parse().ok()?))),
+        None => Some((s.trim().parse().ok()?, None)),
+    }
+}
+
 impl Monitor for NetSysfsQueuesMonitor {
     fn name(&self) -> &'static &str {
         &"net_sysfs_queues"
@@ -120,6 +251,8 @@ impl Monitor for NetSysfsQueuesMonitor {
     fn collect(&mut self) -> Result<()> {
         let mut if_count = 0usize;
         let mut q_count = 0usize;
+        let mut cpus = HashSet::new();
+        let mut rxqs = HashSet::new();
 
         let entries =
             fs::read_dir(&self.root).with_context(|| format!("reading net class directory: {:?}", self.root))?;
@@ -151,7 +284,7 @@ impl Monitor for NetSysfsQueuesMonitor {
                 };
 
                 let qdir = q.path();
-                let added = self.scrape_queue_dir(&iface, qtype, qid, &qdir)?;
+                let added = self.scrape_queue_dir(&iface, qtype, qid, &qdir, &mut cpus, &mut rxqs)?;
                 if added > 0 {
                     q_count += 1;
                 }
@@ -160,7 +293,40 @@ impl Monitor for NetSysfsQueuesMonitor {
             if_count += 1;
         }
 
-        debug!("net_sysfs_queues: updated {if_count} ifaces, {q_count} queues (numeric files only)");
+        for labels in self.exported_cpus.difference(&cpus) {
+            let _ = self.cpus.remove_label_values(&labels.each_ref().map(String::as_str));
+        }
+        for labels in self.exported_rxqs.difference(&rxqs) {
+            let _ = self.rxqs.remove_label_values(&labels.each_ref().map(String::as_str));
+        }
+        self.exported_cpus = cpus;
+        self.exported_rxqs = rxqs;
+
+        debug!("net_sysfs_queues: updated {if_count} ifaces, {q_count} queues");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mask_words() {
+        // most significant word first: bit 0 of the last word is CPU 0
+        assert_eq!(
+            parse_mask("ff,00000001\n"),
+            Some(vec![0, 32, 33, 34, 35, 36, 37, 38, 39])
+        );
+        assert_eq!(parse_mask("00000000"), Some(vec![]));
+        assert_eq!(parse_mask("0c"), Some(vec![2, 3]));
+        assert_eq!(parse_mask("zz"), None);
+    }
+
+    #[test]
+    fn parse_traffic_class_of_subordinate_devices() {
+        assert_eq!(parse_traffic_class("2\n"), Some((2, None)));
+        assert_eq!(parse_traffic_class("0-1\n"), Some((0, Some(1))));
+        assert_eq!(parse_traffic_class("-1"), None);
+    }
+}
@@ -1,66 +1,130 @@
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, IntCounterVec, Opts, Registry};
+
+use crate::monitor::{Monitor, SnmpProto};
+
+// Exposes /proc/net/snmp as:
+//   snmp{proto="ip|icmp|icmpmsg|tcp|udp|udplite", key} <counter>
+//   snmp_gauge{proto, key}                              <value> for the keys in GAUGE_KEYS
+//   snmp_tcp{key}, snmp_udp{key}                        every Tcp/Udp key as a gauge, kept for compatibility
+
+// keys that are settings or current values rather than counters
+const GAUGE_KEYS: &[(SnmpProto, &str)] = &[
+    (SnmpProto::Ip, "Forwarding"),
+    (SnmpProto::Ip, "DefaultTTL"),
+    (SnmpProto::Tcp, "RtoAlgorithm"),
+    (SnmpProto::Tcp, "RtoMin"),
+    (SnmpProto::Tcp, "RtoMax"),
+    (SnmpProto::Tcp, "MaxConn"),
+    (SnmpProto::Tcp, "CurrEstab"),
+];
+
+/// Counters mirroring monotonic kernel values, advanced by the difference to the last read.
+pub struct KernelCounters {
+    vec: IntCounterVec,
+    prev: HashMap<(String, String), u64>,
+}
 
-use anyhow::{Context, Ok};
-use prometheus::{GaugeVec, Opts, Registry};
+impl KernelCounters {
+    pub fn new(registry: &Registry, name: &str, help: &str) -> Result<Self> {
+        let vec = IntCounterVec::new(Opts::new(name, help), &["proto", "key"])?;
+        registry.register(Box::new(vec.clone()))?;
+        Ok(Self {
+            vec,
+            prev: HashMap::new(),
+        })
+    }
 
-use crate::monitor::Monitor;
+    /// Moves the counter to `val`, recreating it if the kernel value went backwards.
+    pub fn advance(&mut self, proto: &str, key: &str, val: u64) {
+        let labels = [proto, key];
+        match self.prev.insert((proto.to_string(), key.to_string()), val) {
+            Some(prev) if val >= prev => self.vec.with_label_values(&labels).inc_by(val - prev),
+            _ => {
+                let _ = self.vec.remove_label_values(&labels);
+                self.vec.with_label_values(&labels).inc_by(val);
+            }
+        }
+    }
+}
 
 pub struct SNMPMonitor {
     path: PathBuf,
+    // empty exports every section
+    protos: Vec<SnmpProto>,
     udp: GaugeVec,
     tcp: GaugeVec,
+    counters: KernelCounters,
+    gauges: GaugeVec,
 }
 
 impl SNMPMonitor {
-    pub fn new(registry: &Registry) -> anyhow::Result<Self> {
+    pub fn new(registry: &Registry, protos: Vec<SnmpProto>) -> anyhow::Result<Self> {
         let tcp = GaugeVec::new(Opts::new("snmp_tcp", "TCP Stats from /proc/net/snmp"), &["key"])?;
         registry.register(Box::new(tcp.clone()))?;
 
         let udp = GaugeVec::new(Opts::new("snmp_udp", "UDP Stats from /proc/net/snmp"), &["key"])?;
         registry.register(Box::new(udp.clone()))?;
 
+        let counters = KernelCounters::new(registry, "snmp", "Protocol counters from /proc/net/snmp")?;
+
+        let gauges = GaugeVec::new(
+            Opts::new(
+                "snmp_gauge",
+                "Protocol settings and current values from /proc/net/snmp (e.g. Tcp MaxConn, RtoMin, CurrEstab)",
+            ),
+            &["proto", "key"],
+        )?;
+        registry.register(Box::new(gauges.clone()))?;
+
         Ok(Self {
             path: PathBuf::from("/proc/net/snmp"),
+            protos,
             tcp,
             udp,
+            counters,
+            gauges,
         })
     }
 
-    fn parse_snmp_pairs(&self) -> anyhow::Result<Vec<(String, String, f64)>> {
-        let content = fs::read_to_string(&self.path).unwrap();
-
-        let mut out = Vec::new();
-        let mut lines = content.lines();
+    fn is_gauge(proto: SnmpProto, key: &str) -> bool {
+        GAUGE_KEYS.iter().any(|(p, k)| *p == proto && *k == key)
+    }
+}
 
-        while let Some(hdr) = lines.next() {
-            let Some(vals) = lines.next() else { break };
+/// Parses the header/value line pairs used by /proc/net/snmp and /proc/net/netstat,
+/// e.g. "Tcp: RtoAlgorithm RtoMin ..." followed by "Tcp: 1 200 ...", into (section, key, value).
+pub fn parse_snmp_pairs(content: &str) -> Result<Vec<(&str, &str, f64)>> {
+    let mut out = Vec::new();
+    let mut lines = content.lines();
 
-            let (proto_h, keys_str) = hdr.split_once(":").context("bad header line in /proc/net/snmp")?;
-            let (proto_v, vals_str) = vals.split_once(":").context("bad value line in /proc/net/snmp")?;
+    while let Some(hdr) = lines.next() {
+        let Some(vals) = lines.next() else { break };
 
-            let proto = proto_h.trim();
-            if proto != proto_v.trim() {
-                continue;
-            }
+        let (proto_h, keys_str) = hdr.split_once(":").context("bad header line")?;
+        let (proto_v, vals_str) = vals.split_once(":").context("bad value line")?;
 
-            if proto != "Tcp" && proto != "Udp" {
-                continue;
-            }
-
-            let keys: Vec<&str> = keys_str.split_whitespace().collect();
-            let vals: Vec<&str> = vals_str.split_whitespace().collect();
-            if keys.len() != vals.len() {
-                continue;
-            }
+        let proto = proto_h.trim();
+        if proto != proto_v.trim() {
+            continue;
+        }
 
-            for (k, v) in keys.into_iter().zip(vals.into_iter()) {
-                let n = v.parse::<f64>()?;
-                out.push((proto.to_string(), k.to_string(), n));
-            }
+        let keys: Vec<&str> = keys_str.split_whitespace().collect();
+        let vals: Vec<&str> = vals_str.split_whitespace().collect();
+        if keys.len() != vals.len() {
+            continue;
         }
 
-        Ok(out)
+        for (k, v) in keys.into_iter().zip(vals.into_iter()) {
+            let n = v.parse::<f64>().with_context(|| format!("parsing {proto} {k}"))?;
+            out.push((proto, k, n));
+        }
     }
+
+    Ok(out)
 }
 
 impl Monitor for SNMPMonitor {
@@ -69,17 +133,29 @@ impl Monitor for SNMPMonitor {
     }
 
     fn collect(&mut self) -> anyhow::Result<()> {
-        for (proto, key, val) in self.parse_snmp_pairs()? {
-            match proto.as_str() {
-                // Replace with enum
-                "Tcp" => {
-                    self.tcp.with_label_values(&[key.to_string()]).set(val);
-                }
-                "Udp" => {
-                    self.udp.with_label_values(&[key.to_string()]).set(val);
-                }
+        let content = fs::read_to_string(&self.path).with_context(|| format!("reading {:?}", self.path))?;
+        let pairs = parse_snmp_pairs(&content).with_context(|| format!("parsing {:?}", self.path))?;
+
+        for (section, key, val) in pairs {
+            let Some(proto) = SnmpProto::from_section(section) else {
+                debug!("snmp: skipping unknown section {section}");
+                continue;
+            };
+
+            match proto {
+                SnmpProto::Tcp => self.tcp.with_label_values(&[key]).set(val),
+                SnmpProto::Udp => self.udp.with_label_values(&[key]).set(val),
                 _ => {}
             }
+
+            if !self.protos.is_empty() && !self.protos.contains(&proto) {
+                continue;
+            }
+            if Self::is_gauge(proto, key) {
+                self.gauges.with_label_values(&[proto.as_str(), key]).set(val);
+            } else {
+                self.counters.advance(proto.as_str(), key, val as u64);
+            }
         }
 
         Ok(())
@@ -0,0 +1,105 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+
+use crate::monitor::Monitor;
+use crate::monitors::snmp::KernelCounters;
+
+// Exposes the IPv6 stack counters of /proc/net/snmp6 as:
+//   snmp6{proto="ip6|icmp6|udp6|udplite6", key="InReceives|..."} <counter>
+// Unlike /proc/net/snmp the file has one "Ip6InReceives   7" pair per line, so the
+// protocol is split off the front of the key.
+
+const PROTOS: &[(&str, &str)] = &[
+    ("UdpLite6", "udplite6"),
+    ("Icmp6", "icmp6"),
+    ("Udp6", "udp6"),
+    ("Ip6", "ip6"),
+];
+
+pub struct Snmp6Monitor {
+    path: PathBuf,
+    counters: KernelCounters,
+    // set once the file turned out to be missing (IPv6 disabled), so it is reported once
+    missing: bool,
+}
+
+impl Snmp6Monitor {
+    pub fn new(registry: &prometheus::Registry) -> Result<Self> {
+        Ok(Self {
+            path: PathBuf::from("/proc/net/snmp6"),
+            counters: KernelCounters::new(registry, "snmp6", "IPv6 protocol counters from /proc/net/snmp6")?,
+            missing: false,
+        })
+    }
+
+    fn parse_line(line: &str) -> Option<(&'static str, &str, u64)> {
+        let (name, val) = line.split_once(char::is_whitespace)?;
+        let (prefix, proto) = PROTOS.iter().find(|(p, _)| name.starts_with(p))?;
+        Some((proto, &name[prefix.len()..], val.trim().parse().ok()?))
+    }
+}
+
+impl Monitor for Snmp6Monitor {
+    fn name(&self) -> &'static &str {
+        &"snmp6"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let content = match fs::read_to_string(&self.path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                if !self.missing {
+                    warn!("snmp6: {:?} not found, IPv6 is probably disabled", self.path);
+                    self.missing = true;
+                }
+                return Ok(());
+            }
+            res => res.with_context(|| format!("reading {:?}", self.path))?,
+        };
+
+        for line in content.lines() {
+            match Self::parse_line(line) {
+                Some((proto, key, val)) => self.counters.advance(proto, key, val),
+                None => debug!("snmp6: skipping line {line:?}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_splits_protocol_prefix() {
+        let parse = Snmp6Monitor::parse_line;
+        assert_eq!(
+            parse("Ip6InReceives                   	7"),
+            Some(("ip6", "InReceives", 7))
+        );
+        assert_eq!(
+            parse("Icmp6OutMsgs                    	12"),
+            Some(("icmp6", "OutMsgs", 12))
+        );
+        assert_eq!(
+            parse("Udp6InDatagrams                 	3"),
+            Some(("udp6", "InDatagrams", 3))
+        );
+        assert_eq!(
+            parse("UdpLite6InDatagrams             	0"),
+            Some(("udplite6", "InDatagrams", 0))
+        );
+    }
+
+    #[test]
+    fn parse_line_rejects_malformed_lines() {
+        let parse = Snmp6Monitor::parse_line;
+        assert_eq!(parse("Ip6InReceives"), None);
+        assert_eq!(parse("Ip6InReceives                   	x"), None);
+        assert_eq!(parse("Tcp6InSegs                      	5"), None);
+        assert_eq!(parse(""), None);
+    }
+}
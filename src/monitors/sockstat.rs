@@ -0,0 +1,192 @@
+use std::{collections::HashMap, fs};
+
+use anyhow::{Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+
+// Socket usage and protocol memory pressure:
+//   sockstat{proto="sockets|tcp|udp|frag|tcp6|...", key="used|inuse|orphan|tw|alloc|mem|memory"}
+//       from /proc/net/sockstat and /proc/net/sockstat6 (tcp/udp "mem" is in pages)
+//   sockstat_mem_limit_pages{proto="tcp|udp", threshold="min|pressure|max"}
+//       net.ipv4.tcp_mem / udp_mem
+//   sockstat_mem_pressure_ratio{proto="tcp|udp"}
+//       "mem" divided by the pressure threshold; the kernel starts reclaiming at 1
+//   net_protocol_sockets{protocol}, net_protocol_memory_pages{protocol}, net_protocol_pressure{protocol}
+//       from /proc/net/protocols, for protocols that account memory
+
+const SOCKSTAT_FILES: &[&str] = &["/proc/net/sockstat", "/proc/net/sockstat6"];
+const MEM_SYSCTLS: &[(&str, &str)] = &[
+    ("tcp", "/proc/sys/net/ipv4/tcp_mem"),
+    ("udp", "/proc/sys/net/ipv4/udp_mem"),
+];
+const THRESHOLDS: &[&str] = &["min", "pressure", "max"];
+
+pub struct SockstatMonitor {
+    sockstat: GaugeVec,
+    mem_limit: GaugeVec,
+    pressure_ratio: GaugeVec,
+    proto_sockets: GaugeVec,
+    proto_memory: GaugeVec,
+    proto_pressure: GaugeVec,
+}
+
+impl SockstatMonitor {
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let gauge = |name: &str, help: &str, labels: &[&str]| -> Result<GaugeVec> {
+            let g = GaugeVec::new(Opts::new(name, help), labels)?;
+            registry.register(Box::new(g.clone()))?;
+            Ok(g)
+        };
+
+        Ok(Self {
+            sockstat: gauge(
+                "sockstat",
+                "Socket counts and memory from /proc/net/sockstat and sockstat6 (mem in pages)",
+                &["proto", "key"],
+            )?,
+            mem_limit: gauge(
+                "sockstat_mem_limit_pages",
+                "net.ipv4.tcp_mem / udp_mem thresholds in pages",
+                &["proto", "threshold"],
+            )?,
+            pressure_ratio: gauge(
+                "sockstat_mem_pressure_ratio",
+                "Protocol memory in use divided by the tcp_mem / udp_mem pressure threshold",
+                &["proto"],
+            )?,
+            proto_sockets: gauge(
+                "net_protocol_sockets",
+                "Sockets in use per protocol from /proc/net/protocols",
+                &["protocol"],
+            )?,
+            proto_memory: gauge(
+                "net_protocol_memory_pages",
+                "Memory allocated per protocol from /proc/net/protocols, in pages",
+                &["protocol"],
+            )?,
+            proto_pressure: gauge(
+                "net_protocol_pressure",
+                "1 if the protocol is under memory pressure, from /proc/net/protocols",
+                &["protocol"],
+            )?,
+        })
+    }
+
+    /// Parses lines like "TCP: inuse 4 orphan 0 tw 2 alloc 4 mem 0" into (proto, key, value).
+    fn parse_sockstat(content: &str) -> Vec<(String, &str, f64)> {
+        let mut out = Vec::new();
+        for line in content.lines() {
+            let Some((proto, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let proto = proto.trim().to_ascii_lowercase();
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            for pair in fields.chunks_exact(2) {
+                if let Ok(v) = pair[1].parse::<f64>() {
+                    out.push((proto.clone(), pair[0], v));
+                }
+            }
+        }
+        out
+    }
+
+    fn read_mem_sysctl(path: &str) -> Result<Vec<f64>> {
+        let content = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        content
+            .split_whitespace()
+            .map(|v| v.parse::<f64>().with_context(|| format!("parsing {path}")))
+            .collect()
+    }
+
+    fn collect_protocols(&self) -> Result<()> {
+        let path = "/proc/net/protocols";
+        let content = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        let mut lines = content.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .context("empty /proc/net/protocols")?
+            .split_whitespace()
+            .collect();
+        let col = |name: &str| {
+            header
+                .iter()
+                .position(|h| *h == name)
+                .with_context(|| format!("no {name} column"))
+        };
+        let (sockets_i, memory_i, press_i) = (col("sockets")?, col("memory")?, col("press")?);
+
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (Some(proto), Some(sockets), Some(memory), Some(press)) = (
+                fields.first(),
+                fields.get(sockets_i),
+                fields.get(memory_i),
+                fields.get(press_i),
+            ) else {
+                continue;
+            };
+            // memory is -1 and press "NI" for protocols without memory accounting
+            if *memory == "-1" {
+                continue;
+            }
+
+            if let Ok(v) = sockets.parse::<f64>() {
+                self.proto_sockets.with_label_values(&[proto]).set(v);
+            }
+            if let Ok(v) = memory.parse::<f64>() {
+                self.proto_memory.with_label_values(&[proto]).set(v);
+            }
+            match *press {
+                "yes" => self.proto_pressure.with_label_values(&[proto]).set(1.0),
+                "no" => self.proto_pressure.with_label_values(&[proto]).set(0.0),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Monitor for SockstatMonitor {
+    fn name(&self) -> &'static &str {
+        &"sockstat"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        // (proto, pages) of tcp/udp "mem", for the pressure ratio
+        let mut mem: HashMap<String, f64> = HashMap::new();
+
+        for path in SOCKSTAT_FILES {
+            let content = match fs::read_to_string(path) {
+                Ok(c) => c,
+                // sockstat6 is missing without IPv6
+                Err(e) if path.ends_with('6') => {
+                    debug!("sockstat: reading {path}: {e}");
+                    continue;
+                }
+                Err(e) => return Err(e).with_context(|| format!("reading {path}")),
+            };
+            for (proto, key, val) in Self::parse_sockstat(&content) {
+                if key == "mem" {
+                    mem.insert(proto.clone(), val);
+                }
+                self.sockstat.with_label_values(&[proto.as_str(), key]).set(val);
+            }
+        }
+
+        for (proto, path) in MEM_SYSCTLS {
+            let limits = Self::read_mem_sysctl(path)?;
+            for (threshold, v) in THRESHOLDS.iter().zip(&limits) {
+                self.mem_limit.with_label_values(&[proto, threshold]).set(*v);
+            }
+            if let (Some(used), Some(pressure)) = (mem.get(*proto), limits.get(1)) {
+                if *pressure > 0.0 {
+                    self.pressure_ratio.with_label_values(&[proto]).set(used / pressure);
+                }
+            }
+        }
+
+        self.collect_protocols()
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::ErrorKind,
+};
+
+use anyhow::{Context, Result};
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::Monitor;
+
+// Summaries of the socket tables of the exporter's network namespace:
+//   socket_table_sockets{proto="tcp|tcp6|udp|udp6", state="established|listen|close|..."}  <count>
+//   socket_table_queue_bytes{proto, queue="rx|tx"}        summed queues, LISTEN sockets excluded
+// and, for the ports given with --socket-port:
+//   socket_listen_accept_queue{proto, port}               connections waiting for accept()
+//   socket_udp_drops{proto, port}                         datagrams dropped on the port's sockets
+// Sockets sharing a port (SO_REUSEPORT) are summed.
+
+// index is the kernel's TCP state number (include/net/tcp_states.h); UDP uses
+// established for connected and close for unconnected sockets
+pub const TCP_STATES: &[&str] = &[
+    "",
+    "established",
+    "syn_sent",
+    "syn_recv",
+    "fin_wait1",
+    "fin_wait2",
+    "time_wait",
+    "close",
+    "close_wait",
+    "last_ack",
+    "listen",
+    "closing",
+    "new_syn_recv",
+];
+const TCP_LISTEN: usize = 10;
+const TABLES: &[&str] = &["tcp", "tcp6", "udp", "udp6"];
+
+/// One row of /proc/net/{tcp,udp}[6].
+pub struct SocketEntry {
+    pub local_port: u16,
+    pub state: usize,
+    pub tx_queue: u64,
+    pub rx_queue: u64,
+    pub inode: u64,
+    // UDP only
+    pub drops: Option<u64>,
+}
+
+impl SocketEntry {
+    fn parse(line: &str) -> Option<Self> {
+        // "sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode ..."
+        // UDP rows end in "ref pointer drops"
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (_, port) = fields.get(1)?.rsplit_once(':')?;
+        let (tx, rx) = fields.get(4)?.split_once(':')?;
+        Some(Self {
+            local_port: u16::from_str_radix(port, 16).ok()?,
+            state: usize::from_str_radix(fields.get(3)?, 16).ok()?,
+            tx_queue: u64::from_str_radix(tx, 16).ok()?,
+            rx_queue: u64::from_str_radix(rx, 16).ok()?,
+            inode: fields.get(9)?.parse().ok()?,
+            drops: fields.get(12).and_then(|d| d.parse().ok()),
+        })
+    }
+}
+
+/// Reads a socket table such as /proc/net/tcp6; a missing IPv6 table reads as empty.
+pub fn read_socket_table(path: &str) -> Result<Vec<SocketEntry>> {
+    let content = match fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound && path.ends_with('6') => return Ok(Vec::new()),
+        res => res.with_context(|| format!("reading {path}"))?,
+    };
+    Ok(content.lines().skip(1).filter_map(SocketEntry::parse).collect())
+}
+
+pub struct SocketTableMonitor {
+    ports: Vec<u16>,
+    sockets: GaugeVec,
+    queues: GaugeVec,
+    accept_queue: GaugeVec,
+    udp_drops: GaugeVec,
+    // (proto, port) per-port series written on the previous tick
+    exported: BTreeSet<(&'static str, u16)>,
+}
+
+impl SocketTableMonitor {
+    pub fn new(registry: &Registry, ports: Vec<u16>) -> Result<Self> {
+        let gauge = |name: &str, help: &str, labels: &[&str]| -> Result<GaugeVec> {
+            let g = GaugeVec::new(Opts::new(name, help), labels)?;
+            registry.register(Box::new(g.clone()))?;
+            Ok(g)
+        };
+
+        Ok(Self {
+            ports,
+            sockets: gauge(
+                "socket_table_sockets",
+                "Sockets in /proc/net/{tcp,tcp6,udp,udp6} by state",
+                &["proto", "state"],
+            )?,
+            queues: gauge(
+                "socket_table_queue_bytes",
+                "Summed rx/tx queue bytes of non-listening sockets in /proc/net/{tcp,tcp6,udp,udp6}",
+                &["proto", "queue"],
+            )?,
+            accept_queue: gauge(
+                "socket_listen_accept_queue",
+                "Connections waiting in the accept queue of LISTEN sockets on the port",
+                &["proto", "port"],
+            )?,
+            udp_drops: gauge(
+                "socket_udp_drops",
+                "Datagrams dropped by UDP sockets bound to the port",
+                &["proto", "port"],
+            )?,
+            exported: BTreeSet::new(),
+        })
+    }
+}
+
+impl Monitor for SocketTableMonitor {
+    fn name(&self) -> &'static &str {
+        &"socket_table"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let mut exported = BTreeSet::new();
+
+        for &proto in TABLES {
+            let entries = read_socket_table(&format!("/proc/net/{proto}"))?;
+
+            let mut states = vec![0u64; TCP_STATES.len()];
+            let (mut rx, mut tx) = (0u64, 0u64);
+            // port -> (accept queue, udp drops)
+            let mut per_port: BTreeMap<u16, (u64, u64)> = BTreeMap::new();
+
+            for s in &entries {
+                if let Some(n) = states.get_mut(s.state) {
+                    *n += 1;
+                }
+                let listening = s.state == TCP_LISTEN && proto.starts_with("tcp");
+                // on LISTEN sockets rx_queue holds the accept queue length
+                if !listening {
+                    rx += s.rx_queue;
+                    tx += s.tx_queue;
+                }
+                if !self.ports.contains(&s.local_port) {
+                    continue;
+                }
+                let p = per_port.entry(s.local_port).or_default();
+                if listening {
+                    p.0 += s.rx_queue;
+                }
+                p.1 += s.drops.unwrap_or(0);
+            }
+
+            for (state, n) in TCP_STATES.iter().zip(states).skip(1) {
+                self.sockets.with_label_values(&[proto, state]).set(n as f64);
+            }
+            self.queues.with_label_values(&[proto, "rx"]).set(rx as f64);
+            self.queues.with_label_values(&[proto, "tx"]).set(tx as f64);
+
+            for (port, (queue, drops)) in per_port {
+                let port_s = port.to_string();
+                let labels = [proto, port_s.as_str()];
+                if proto.starts_with("tcp") {
+                    self.accept_queue.with_label_values(&labels).set(queue as f64);
+                } else {
+                    self.udp_drops.with_label_values(&labels).set(drops as f64);
+                }
+                exported.insert((proto, port));
+            }
+        }
+
+        // ports whose sockets were closed
+        for (proto, port) in self.exported.difference(&exported) {
+            let port_s = port.to_string();
+            let labels = [*proto, port_s.as_str()];
+            for g in [&self.accept_queue, &self.udp_drops] {
+                let _ = g.remove_label_values(&labels);
+            }
+        }
+        self.exported = exported;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use prometheus::{GaugeVec, Opts, Registry};
+
+use crate::monitor::{Monitor, TcpDiagBy};
+use crate::monitors::netlink::{attrs, NetlinkSocket};
+
+// Per-connection TCP health from the kernel's tcp_info, dumped over NETLINK_SOCK_DIAG
+// (INET_DIAG_INFO) for connections whose local port is in --tcp-diag-sport or whose
+// remote port is in --tcp-diag-dport; the ports are compiled into an INET_DIAG_REQ_BYTECODE
+// filter the way `ss` does, so the kernel skips every other socket:
+//   tcp_diag_conn{local="addr:port", peer="addr:port", key}    with --tcp-diag-by connection
+//   tcp_diag_peer{peer="addr", key}                            with --tcp-diag-by peer
+// Peer aggregation averages the rtt/cwnd keys and sums the rest, and adds key="connections".
+// Layouts follow include/uapi/linux/inet_diag.h, sock_diag.h and tcp.h.
+
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+const INET_DIAG_REQ_BYTECODE: u16 = 1;
+const INET_DIAG_INFO: u16 = 2;
+
+// struct inet_diag_bc_op codes
+const INET_DIAG_BC_JMP: u8 = 1;
+const INET_DIAG_BC_S_GE: u8 = 2;
+const INET_DIAG_BC_S_LE: u8 = 3;
+const INET_DIAG_BC_D_GE: u8 = 4;
+const INET_DIAG_BC_D_LE: u8 = 5;
+// struct inet_diag_req_v2: family, protocol, ext, pad, states, inet_diag_sockid (48 bytes)
+const REQ_LEN: usize = 56;
+// struct inet_diag_msg: family, state, timer, retrans, inet_diag_sockid, expires, rqueue, wqueue, uid, inode
+const DIAG_MSG_LEN: usize = 72;
+
+const TCP_TIME_WAIT: u32 = 6;
+const TCP_LISTEN: u32 = 10;
+
+enum Agg {
+    Sum,
+    Avg,
+}
+
+/// (key, offset in struct tcp_info, width in bytes, scale, aggregation per peer)
+const TCP_INFO_KEYS: &[(&str, usize, usize, f64, Agg)] = &[
+    ("lost", 32, 4, 1.0, Agg::Sum),
+    ("retrans", 36, 4, 1.0, Agg::Sum),
+    ("rtt_seconds", 68, 4, 1e-6, Agg::Avg),
+    ("rttvar_seconds", 72, 4, 1e-6, Agg::Avg),
+    ("snd_cwnd", 80, 4, 1.0, Agg::Avg),
+    ("total_retrans", 100, 4, 1.0, Agg::Sum),
+    ("min_rtt_seconds", 148, 4, 1e-6, Agg::Avg),
+    ("delivery_rate_bytes", 160, 8, 1.0, Agg::Sum),
+    ("busy_seconds", 168, 8, 1e-6, Agg::Sum),
+    ("rwnd_limited_seconds", 176, 8, 1e-6, Agg::Sum),
+    ("sndbuf_limited_seconds", 184, 8, 1e-6, Agg::Sum),
+];
+
+struct Connection {
+    local: String,
+    peer_addr: IpAddr,
+    peer: String,
+    // values of TCP_INFO_KEYS present in this kernel's tcp_info
+    info: Vec<Option<f64>>,
+}
+
+pub struct TcpDiagMonitor {
+    sock: NetlinkSocket,
+    sports: Vec<u16>,
+    dports: Vec<u16>,
+    by: TcpDiagBy,
+    conn: GaugeVec,
+    peer: GaugeVec,
+    // label sets written on the previous tick, to drop closed connections and gone peers
+    seen: HashSet<Vec<String>>,
+}
+
+impl TcpDiagMonitor {
+    pub fn new(registry: &Registry, sports: Vec<u16>, dports: Vec<u16>, by: TcpDiagBy) -> Result<Self> {
+        if sports.is_empty() && dports.is_empty() {
+            bail!("tcp_diag: needs --tcp-diag-sport or --tcp-diag-dport to select connections");
+        }
+
+        let sock = NetlinkSocket::open(libc::NETLINK_SOCK_DIAG)?;
+
+        let conn = GaugeVec::new(
+            Opts::new("tcp_diag_conn", "tcp_info of selected TCP connections, from sock_diag"),
+            &["local", "peer", "key"],
+        )?;
+        registry.register(Box::new(conn.clone()))?;
+
+        let peer = GaugeVec::new(
+            Opts::new(
+                "tcp_diag_peer",
+                "tcp_info of selected TCP connections aggregated per remote address, from sock_diag",
+            ),
+            &["peer", "key"],
+        )?;
+        registry.register(Box::new(peer.clone()))?;
+
+        Ok(Self {
+            sock,
+            sports,
+            dports,
+            by,
+            conn,
+            peer,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// inet_diag_req_v2 for the TCP sockets of one address family, with the port filter.
+    fn request(&self, family: u8) -> Vec<u8> {
+        let states: u32 = 0xfff & !(1 << TCP_LISTEN) & !(1 << TCP_TIME_WAIT);
+        let mut req = Vec::with_capacity(REQ_LEN);
+        req.extend_from_slice(&[family, libc::IPPROTO_TCP as u8, 1 << (INET_DIAG_INFO - 1), 0]);
+        req.extend_from_slice(&states.to_ne_bytes());
+        req.resize(REQ_LEN, 0);
+
+        let bc = port_filter(&self.sports, &self.dports);
+        req.extend_from_slice(&((4 + bc.len()) as u16).to_ne_bytes());
+        req.extend_from_slice(&INET_DIAG_REQ_BYTECODE.to_ne_bytes());
+        req.extend_from_slice(&bc);
+        req
+    }
+
+    /// Dumps the TCP sockets of one address family, keeping the selected connections.
+    fn dump(&mut self, family: u8, out: &mut Vec<Connection>) -> Result<()> {
+        let req = self.request(family);
+        let (sports, dports) = (&self.sports, &self.dports);
+        self.sock
+            .dump(SOCK_DIAG_BY_FAMILY, &req, |_, m| {
+                if let Some(c) = parse_msg(m, sports, dports) {
+                    out.push(c);
+                }
+            })
+            .context("inet_diag dump")
+    }
+
+    fn export_connections(&self, conns: &[Connection], seen: &mut HashSet<Vec<String>>) {
+        for c in conns {
+            for ((key, ..), v) in TCP_INFO_KEYS.iter().zip(&c.info) {
+                if let Some(v) = v {
+                    self.conn
+                        .with_label_values(&[c.local.as_str(), c.peer.as_str(), key])
+                        .set(*v);
+                }
+            }
+            seen.insert(vec![c.local.clone(), c.peer.clone()]);
+        }
+    }
+
+    fn export_peers(&self, conns: &[Connection], seen: &mut HashSet<Vec<String>>) {
+        // peer -> (connections, per key (sum, samples))
+        let mut peers: BTreeMap<IpAddr, (usize, Vec<(f64, usize)>)> = BTreeMap::new();
+        for c in conns {
+            let (n, sums) = peers
+                .entry(c.peer_addr)
+                .or_insert_with(|| (0, vec![(0.0, 0); TCP_INFO_KEYS.len()]));
+            *n += 1;
+            for ((sum, samples), v) in sums.iter_mut().zip(&c.info) {
+                if let Some(v) = v {
+                    *sum += v;
+                    *samples += 1;
+                }
+            }
+        }
+
+        for (addr, (n, sums)) in peers {
+            let peer = addr.to_string();
+            self.peer
+                .with_label_values(&[peer.as_str(), "connections"])
+                .set(n as f64);
+            for ((key, .., agg), (sum, samples)) in TCP_INFO_KEYS.iter().zip(sums) {
+                if samples == 0 {
+                    continue;
+                }
+                let v = match agg {
+                    Agg::Sum => sum,
+                    Agg::Avg => sum / samples as f64,
+                };
+                self.peer.with_label_values(&[peer.as_str(), key]).set(v);
+            }
+            seen.insert(vec![peer]);
+        }
+    }
+}
+
+impl Monitor for TcpDiagMonitor {
+    fn name(&self) -> &'static &str {
+        &"tcp_diag"
+    }
+
+    fn collect(&mut self) -> Result<()> {
+        let mut conns = Vec::new();
+        self.dump(libc::AF_INET as u8, &mut conns)?;
+        self.dump(libc::AF_INET6 as u8, &mut conns)?;
+
+        let mut seen = HashSet::new();
+        match self.by {
+            TcpDiagBy::Connection => self.export_connections(&conns, &mut seen),
+            TcpDiagBy::Peer => self.export_peers(&conns, &mut seen),
+        }
+
+        for gone in self.seen.difference(&seen) {
+            let keys = TCP_INFO_KEYS.iter().map(|(k, ..)| *k).chain(["connections"]);
+            for key in keys {
+                let mut labels: Vec<&str> = gone.iter().map(String::as_str).collect();
+                labels.push(key);
+                let _ = match self.by {
+                    TcpDiagBy::Connection => self.conn.remove_label_values(&labels),
+                    TcpDiagBy::Peer => self.peer.remove_label_values(&labels),
+                };
+            }
+        }
+
+        debug!("tcp_diag: {} selected connections", conns.len());
+        self.seen = seen;
+        Ok(())
+    }
+}
+
+/// inet_diag bytecode accepting sockets whose local port is in `sports` or remote port is in
+/// `dports`, laid out like `ss` compiles "sport = :a or dport = :b". Every port test falls
+/// through on a match and jumps 4 bytes past its own end otherwise; "x or y" is
+/// x, JMP over y, y, so a failing x lands on y and a failing last test jumps past the end,
+/// which rejects the socket.
+fn port_filter(sports: &[u16], dports: &[u16]) -> Vec<u8> {
+    let op = |code: u8, yes: u8, no: u16| {
+        let mut b = vec![code, yes];
+        b.extend_from_slice(&no.to_ne_bytes());
+        b
+    };
+    // port == p as "port >= p and port <= p"; the >= test skips the <= one when it fails
+    let port_eq =
+        |(ge, le): (u8, u8), port: u16| [op(ge, 8, 20), op(0, 0, port), op(le, 8, 12), op(0, 0, port)].concat();
+
+    let tests = sports
+        .iter()
+        .map(|p| port_eq((INET_DIAG_BC_S_GE, INET_DIAG_BC_S_LE), *p))
+        .chain(
+            dports
+                .iter()
+                .map(|p| port_eq((INET_DIAG_BC_D_GE, INET_DIAG_BC_D_LE), *p)),
+        );
+
+    let mut bc = Vec::new();
+    for test in tests {
+        if !bc.is_empty() {
+            bc.extend(op(INET_DIAG_BC_JMP, 4, test.len() as u16 + 4));
+        }
+        bc.extend(test);
+    }
+    bc
+}
+
+fn parse_msg(m: &[u8], sports: &[u16], dports: &[u16]) -> Option<Connection> {
+    if m.len() < DIAG_MSG_LEN {
+        return None;
+    }
+    // inet_diag_sockid at offset 4: sport, dport (big endian), src[16], dst[16], if, cookie
+    let sport = u16::from_be_bytes([m[4], m[5]]);
+    let dport = u16::from_be_bytes([m[6], m[7]]);
+    if !sports.contains(&sport) && !dports.contains(&dport) {
+        return None;
+    }
+    let addr = |off: usize| -> Option<IpAddr> {
+        let b = m.get(off..off + 16)?;
+        Some(match m[0] as i32 {
+            libc::AF_INET => IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])),
+            _ => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(b).ok()?)),
+        })
+    };
+    let (src, dst) = (addr(8)?, addr(24)?);
+
+    let info = attrs(&m[DIAG_MSG_LEN..]).find(|(t, _)| *t == INET_DIAG_INFO)?.1;
+
+    let values = TCP_INFO_KEYS
+        .iter()
+        .map(|(_, off, width, scale, _)| {
+            let b = info.get(*off..*off + *width)?;
+            let v = match width {
+                4 => u32::from_ne_bytes(b.try_into().ok()?) as f64,
+                _ => u64::from_ne_bytes(b.try_into().ok()?) as f64,
+            };
+            Some(v * scale)
+        })
+        .collect();
+
+    Some(Connection {
+        local: sock_addr(src, sport),
+        peer_addr: dst,
+        peer: sock_addr(dst, dport),
+        info: values,
+    })
+}
+
+fn sock_addr(addr: IpAddr, port: u16) -> String {
+    match addr {
+        IpAddr::V4(a) => format!("{a}:{port}"),
+        IpAddr::V6(a) => format!("[{a}]:{port}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn kernel_filters_by_port_on_loopback() {
+        let selected = TcpListener::bind("127.0.0.1:0").unwrap();
+        let other = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = selected.local_addr().unwrap().port();
+        let client = TcpStream::connect(selected.local_addr().unwrap()).unwrap();
+        let _other_client = TcpStream::connect(other.local_addr().unwrap()).unwrap();
+
+        let mut mon = TcpDiagMonitor::new(&Registry::new(), vec![], vec![port], TcpDiagBy::Connection).unwrap();
+
+        // every socket the kernel returns, without the userspace port check
+        let req = mon.request(libc::AF_INET as u8);
+        let mut dports = Vec::new();
+        mon.sock
+            .dump(SOCK_DIAG_BY_FAMILY, &req, |_, m| {
+                dports.push(u16::from_be_bytes([m[6], m[7]]))
+            })
+            .unwrap();
+        assert_eq!(dports, [port]);
+
+        let mut conns = Vec::new();
+        mon.dump(libc::AF_INET as u8, &mut conns).unwrap();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].peer, format!("127.0.0.1:{port}"));
+        assert!(conns[0].info.iter().any(Option::is_some));
+
+        // several ports of both directions are or'ed
+        let other_port = other.local_addr().unwrap().port();
+        let a_port = client.local_addr().unwrap().port();
+        let mut mon = TcpDiagMonitor::new(
+            &Registry::new(),
+            vec![a_port, 1],
+            vec![2, other_port],
+            TcpDiagBy::Connection,
+        )
+        .unwrap();
+        let mut conns = Vec::new();
+        mon.dump(libc::AF_INET as u8, &mut conns).unwrap();
+        let mut peers: Vec<String> = conns.into_iter().map(|c| c.peer).collect();
+        peers.sort();
+        let mut want = [format!("127.0.0.1:{port}"), format!("127.0.0.1:{other_port}")];
+        want.sort();
+        assert_eq!(peers, want);
+    }
+}